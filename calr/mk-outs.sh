@@ -7,3 +7,5 @@ cal 2020 > $OUTDIR/2020.txt
 cal 2 2020 > $OUTDIR/2-2020.txt
 cal 4 2020 > $OUTDIR/4-2020.txt
 cal 5 2020 > $OUTDIR/5-2020.txt
+cal -j 2 2020 > $OUTDIR/j-2-2020.txt
+cal 9 1752 > $OUTDIR/9-1752.txt
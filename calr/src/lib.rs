@@ -1,20 +1,104 @@
 use ansi_term::Style;
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::{Datelike, Local, NaiveDate, Weekday};
 use clap::{App, Arg};
-use itertools::izip;
+use std::env;
 use std::error::Error;
+use std::fs;
+use std::io::{self, IsTerminal};
 use std::str::FromStr;
+use terminal_size::{terminal_size, Width};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
 #[derive(Debug)]
 pub struct Config {
     month: Option<u32>,
-    year: i32,
-    today: NaiveDate,
+    years: Vec<i32>,
+    today: Option<NaiveDate>,
+    julian: bool,
+    columns: usize,
+    week_start: Weekday,
+    reform: Reform,
+    before: u32,
+    after: u32,
+    iso_week: Option<NaiveDate>,
+    doy: Option<NaiveDate>,
+    diff: Option<(NaiveDate, NaiveDate)>,
+    events: Vec<Event>,
+    legend: bool,
+    compact: bool,
 }
 
-const LINE_WIDTH: usize = 22;
+/// A day to mark, every year unless it has one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub year: Option<i32>,
+    pub month: u32,
+    pub day: u32,
+    pub description: String,
+}
+
+/// When the Julian calendar gave way to the Gregorian one, if ever
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Reform {
+    /// September 2nd, 1752 was followed by the 14th, as in Great Britain
+    Y1752,
+    /// The Gregorian calendar all the way back
+    Gregorian,
+    /// The Julian calendar all the way through, as in Orthodox liturgy
+    Julian,
+}
+
+/// How `format_month` and `format_year` lay out months, from
+/// `MonthFormat::default()` on
+///
+/// ```
+/// use calr::{format_month, MonthFormat};
+/// use chrono::Weekday;
+///
+/// let format = MonthFormat {
+///     week_start: Weekday::Mon,
+///     ..MonthFormat::default()
+/// };
+/// let lines = format_month(2020, 2, true, &format);
+/// assert_eq!(lines[1], "Mo Tu We Th Fr Sa Su  ");
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MonthFormat<'a> {
+    /// The day to highlight in reverse video
+    pub today: Option<NaiveDate>,
+    /// The days to underline
+    pub events: &'a [Event],
+    /// Whether days are numbered from the start of the year
+    pub julian: bool,
+    /// The day weeks start on, Sunday by default
+    pub week_start: Weekday,
+    /// When the calendar changed, in 1752 by default
+    pub reform: Reform,
+}
+
+impl Default for MonthFormat<'_> {
+    fn default() -> Self {
+        MonthFormat {
+            today: None,
+            events: &[],
+            julian: false,
+            week_start: Weekday::Sun,
+            reform: Reform::Y1752,
+        }
+    }
+}
+
+/// Width of the lines of a month
+pub const LINE_WIDTH: usize = 22;
+/// Width of the lines of a month with Julian days
+pub const JULIAN_LINE_WIDTH: usize = 29;
+// Where the week starts on Sunday, going by CLDR; it starts on Monday in
+// most other places
+const SUNDAY_TERRITORIES: [&str; 14] = [
+    "BR", "CA", "HK", "IL", "IN", "JP", "KR", "MX", "PH", "SA", "TW", "US", "ZA", "ZW",
+];
 const MONTH_NAMES: [&str; 12] = [
     "January",
     "February",
@@ -35,12 +119,17 @@ pub fn get_args() -> MyResult<Config> {
         .version("0.1.")
         .author("Felipe Balbi <felipe@balbi.sh")
         .about("Rust cal")
-        .arg(Arg::with_name("year").value_name("YEAR").takes_value(true))
+        .arg(
+            Arg::with_name("year")
+                .value_name("YEAR")
+                .help("Year, or years to show one after the other, or a month and its year")
+                .multiple(true),
+        )
         .arg(
             Arg::with_name("month")
                 .value_name("MONTH")
                 .short("m")
-                .help("Month name or number (1-12)")
+                .help("Month name or number (1-12), or a range of them like 6-9")
                 .takes_value(true),
         )
         .arg(
@@ -50,46 +139,298 @@ pub fn get_args() -> MyResult<Config> {
                 .long("year")
                 .conflicts_with_all(&["month", "year"]),
         )
+        .arg(
+            Arg::with_name("julian")
+                .help("Number days from the start of the year")
+                .short("j"),
+        )
+        .arg(
+            Arg::with_name("monday")
+                .help("Start weeks on Monday")
+                .short("M")
+                .long("monday"),
+        )
+        .arg(
+            Arg::with_name("sunday")
+                .help("Start weeks on Sunday")
+                .short("S")
+                .long("sunday")
+                .conflicts_with("monday"),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .value_name("N")
+                .long("columns")
+                .help("Months per row, fitting the terminal by default")
+                .possible_values(&["2", "3", "4", "6"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("span")
+                .value_name("N")
+                .long("span")
+                .help("Show N months in a row from the month")
+                .takes_value(true)
+                .conflicts_with_all(&["after", "show_year"]),
+        )
+        .arg(
+            Arg::with_name("after")
+                .value_name("N")
+                .help("Also show N months after the month")
+                .short("A")
+                .takes_value(true)
+                .conflicts_with("show_year"),
+        )
+        .arg(
+            Arg::with_name("before")
+                .value_name("N")
+                .help("Also show N months before the month")
+                .short("B")
+                .takes_value(true)
+                .conflicts_with("show_year"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .value_name("WHEN")
+                .long("color")
+                .help("Highlight today")
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::with_name("reform")
+                .value_name("WHEN")
+                .long("reform")
+                .help("Year the Gregorian calendar took over")
+                .possible_values(&["1752", "gregorian", "iso"])
+                .default_value("1752"),
+        )
+        .arg(
+            Arg::with_name("julian_calendar")
+                .long("julian-calendar")
+                .help("Follow the Julian calendar, never reformed"),
+        )
+        .arg(
+            Arg::with_name("iso_week")
+                .value_name("DATE")
+                .long("iso-week")
+                .help("Print the ISO week date of DATE (YYYY-MM-DD), or of today")
+                .takes_value(true)
+                .min_values(0)
+                .conflicts_with_all(&["month", "show_year", "year"]),
+        )
+        .arg(
+            Arg::with_name("doy")
+                .value_name("DATE")
+                .long("doy")
+                .help("Print the day of the year of DATE (YYYY-MM-DD), or of today")
+                .takes_value(true)
+                .min_values(0)
+                .conflicts_with_all(&["iso_week", "month", "show_year", "year"]),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .value_names(&["DATE1", "DATE2"])
+                .long("diff")
+                .help("Print the days between two dates (YYYY-MM-DD)")
+                .number_of_values(2)
+                .conflicts_with_all(&["doy", "iso_week", "month", "show_year", "year"]),
+        )
+        .arg(
+            Arg::with_name("events")
+                .value_name("FILE")
+                .long("events")
+                .help("Mark the days listed in FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("legend")
+                .long("legend")
+                .help("List the events shown below the calendar")
+                .requires("events"),
+        )
+        .arg(
+            Arg::with_name("compact")
+                .long("compact")
+                .help("Leave out the year of months, trailing spaces and blank weeks"),
+        )
         .get_matches();
 
-    let mut month = matches.value_of("month").map(parse_month).transpose()?;
-    let mut year = matches.value_of("year").map(parse_year).transpose()?;
+    let months = matches.value_of("month").map(parse_months).transpose()?;
+    let mut month = months.map(|(first, _)| first);
+    let mut years = None;
+    if let Some(args) = matches.values_of("year") {
+        let (arg_month, arg_years) = parse_month_year(&args.collect::<Vec<_>>())?;
+        if arg_month.is_some() {
+            if month.is_some() {
+                return Err("The month was given both with -m and as an argument".into());
+            }
+            month = arg_month;
+        }
+        years = Some(arg_years);
+    }
+    let before = matches.value_of("before").map(parse_int).transpose()?;
+    let after = matches.value_of("after").map(parse_int).transpose()?;
+
+    let span = matches.value_of("span").map(parse_span).transpose()?;
+
+    // The rest of a range or span comes after its first month
+    let after = match (span, months) {
+        (Some(_), Some((first, last))) if first != last => {
+            return Err("--span cannot be used with a month range".into());
+        }
+        (Some(span), _) => span - 1,
+        _ => after.unwrap_or(0) + months.map_or(0, |(first, last)| last - first),
+    };
+
+    // ISO 8601 has the Gregorian calendar going all the way back
+    let reform = match matches.value_of("reform") {
+        _ if matches.is_present("julian_calendar") => Reform::Julian,
+        Some("1752") => Reform::Y1752,
+        _ => Reform::Gregorian,
+    };
 
     let today = Local::today();
+    // Dates default to today when left out
+    let date_arg = |name: &str| -> MyResult<Option<NaiveDate>> {
+        if !matches.is_present(name) {
+            return Ok(None);
+        }
+        match matches.value_of(name) {
+            Some(date) => parse_date(date, reform).map(Some),
+            None => Ok(Some(today.naive_local())),
+        }
+    };
+    let iso_week = date_arg("iso_week")?;
+    let doy = date_arg("doy")?;
+    let diff = match matches.values_of("diff") {
+        Some(mut dates) => {
+            let (from, to) = (dates.next().unwrap(), dates.next().unwrap());
+            Some((parse_date(from, reform)?, parse_date(to, reform)?))
+        }
+        None => None,
+    };
+
     if matches.is_present("show_year") {
         month = None;
-        year = Some(today.year());
-    } else if month.is_none() && year.is_none() {
+        years = None;
+    } else if month.is_none() && years.is_none() {
         month = Some(today.month());
-        year = Some(today.year());
     }
 
-    let year = year.unwrap_or_else(|| today.year());
-    let today = today.naive_local();
+    let years = years.unwrap_or_else(|| vec![today.year()]);
+    // Escape sequences only belong on a terminal, unless asked for
+    let highlight = match matches.value_of("color") {
+        Some("always") => true,
+        Some("never") => false,
+        _ => io::stdout().is_terminal(),
+    };
+    // Today is a different date for the Julian calendar
+    let today = match reform {
+        Reform::Julian => julian_date(today.naive_local()),
+        _ => Some(today.naive_local()),
+    }
+    .filter(|_| highlight);
 
-    Ok(Config { month, year, today })
+    let events = match matches.value_of("events") {
+        Some(filename) => {
+            let text = fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            parse_events(&text).map_err(|e| format!("{}: {}", filename, e))?
+        }
+        None => vec![],
+    };
+
+    let week_start = if matches.is_present("monday") {
+        Weekday::Mon
+    } else if matches.is_present("sunday") {
+        Weekday::Sun
+    } else {
+        default_week_start()?
+    };
+
+    let julian = matches.is_present("julian");
+    let columns = match matches.value_of("columns") {
+        Some(columns) => parse_int(columns)?,
+        None => auto_columns(julian),
+    };
+
+    Ok(Config {
+        month,
+        years,
+        today,
+        julian,
+        columns,
+        week_start,
+        reform,
+        before: before.unwrap_or(0),
+        after,
+        iso_week,
+        doy,
+        diff,
+        events,
+        legend: matches.is_present("legend"),
+        compact: matches.is_present("compact"),
+    })
 }
 
 pub fn run(config: Config) -> MyResult<()> {
-    if let Some(month) = config.month {
-        let output = format_month(config.year, month, true, config.today);
-
-        println!("{}", output.join("\n"));
+    // Marking days takes escape sequences too
+    let marked: &[Event] = if config.today.is_some() {
+        &config.events
     } else {
-        println!("{:>32}", config.year);
-        let months: Vec<Vec<String>> = (1..=12)
-            .map(|month| format_month(config.year, month, false, config.today))
-            .collect();
+        &[]
+    };
+    let format = MonthFormat {
+        today: config.today,
+        events: marked,
+        julian: config.julian,
+        week_start: config.week_start,
+        reform: config.reform,
+    };
+    let show = |lines: Vec<String>| {
+        let lines = if config.compact {
+            compact(&lines)
+        } else {
+            lines
+        };
+        println!("{}", lines.join("\n"));
+    };
 
-        for (i, month) in months.chunks(3).enumerate() {
-            if let [m1, m2, m3] = month {
-                for lines in izip!(m1, m2, m3) {
-                    println!("{}{}{}", lines.0, lines.1, lines.2);
-                }
+    if let Some(date) = config.iso_week {
+        println!("{}", format_iso_week(date));
+    } else if let Some(date) = config.doy {
+        let (day, days) = day_of_year(date, config.reform);
+        println!("Day {} of {}, {} remaining", day, days, days - day);
+    } else if let Some((from, to)) = config.diff {
+        println!("{}", format_diff(from, to));
+    } else if let Some(month) = config.month {
+        for year in config.years {
+            // Counting months from year 0 makes it easy to go across years
+            let selected = year * 12 + month as i32 - 1;
+            // Staying within the years from 1 through 9999
+            let first = (selected as i64 - config.before as i64).max(12) as i32;
+            let last = (selected as i64 + config.after as i64).min(9999 * 12 + 11) as i32;
+            let months: Vec<Vec<String>> = (first..=last)
+                .map(|num| {
+                    format_month(
+                        num.div_euclid(12),
+                        num.rem_euclid(12) as u32 + 1,
+                        !config.compact,
+                        &format,
+                    )
+                })
+                .collect();
 
-                if i < 3 {
-                    println!();
-                }
+            show(layout(&months, config.columns));
+            if config.legend {
+                print_legend(&config.events, first, last);
+            }
+        }
+    } else {
+        for year in config.years {
+            show(format_year(year, config.columns, &format));
+            if config.legend {
+                print_legend(&config.events, year * 12, year * 12 + 11);
             }
         }
     }
@@ -97,70 +438,415 @@ pub fn run(config: Config) -> MyResult<()> {
     Ok(())
 }
 
-fn last_day_in_month(year: i32, month: u32) -> NaiveDate {
-    let (y, m) = if month == 12 {
-        (year + 1, 1)
+// As many months per row as the terminal fits, keeping rows even. Off a
+// terminal, three to a row or two when they are Julian, like cal.
+fn auto_columns(julian: bool) -> usize {
+    let (default, line_width) = if julian {
+        (2, JULIAN_LINE_WIDTH)
+    } else {
+        (3, LINE_WIDTH)
+    };
+
+    match terminal_size() {
+        Some((Width(width), _)) => [6, 4, 3, 2]
+            .into_iter()
+            .find(|columns| columns * line_width <= width as usize)
+            .unwrap_or(2),
+        None => default,
+    }
+}
+
+// CALR_FIRST_WEEKDAY has the last word, otherwise the locale for dates does
+fn default_week_start() -> MyResult<Weekday> {
+    match env::var("CALR_FIRST_WEEKDAY") {
+        Ok(day) => day
+            .parse()
+            .map_err(|_| format!("Invalid CALR_FIRST_WEEKDAY \"{}\"", day).into()),
+        Err(_) => {
+            let locale = ["LC_ALL", "LC_TIME", "LANG"]
+                .iter()
+                .filter_map(|name| env::var(name).ok())
+                .find(|locale| !locale.is_empty())
+                .unwrap_or_default();
+            Ok(locale_week_start(&locale))
+        }
+    }
+}
+
+// Going by the territory of a locale like en_GB.UTF-8, and like cal for
+// those without one, like C
+fn locale_week_start(locale: &str) -> Weekday {
+    let territory = locale
+        .split(['.', '@'])
+        .next()
+        .and_then(|name| name.split_once('_'))
+        .map(|(_, territory)| territory);
+
+    match territory {
+        Some(territory) if !SUNDAY_TERRITORIES.contains(&territory) => Weekday::Mon,
+        _ => Weekday::Sun,
+    }
+}
+
+// Before the reform, every fourth year was a leap year
+fn days_in_month(year: i32, month: u32, reform: Reform) -> u32 {
+    let julian = match reform {
+        Reform::Y1752 => year <= 1752,
+        Reform::Gregorian => false,
+        Reform::Julian => true,
+    };
+    let leap = if julian {
+        year % 4 == 0
     } else {
-        (year, month + 1)
+        year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
     };
 
-    NaiveDate::from_ymd(y, m, 1).pred()
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
 }
 
-fn format_month(year: i32, month: u32, print_year: bool, today: NaiveDate) -> Vec<String> {
-    let first = NaiveDate::from_ymd(year, month, 1);
-    let mut days: Vec<String> = (1..first.weekday().number_from_sunday())
-        .into_iter()
-        .map(|_| "  ".to_string())
-        .collect();
+// The days from September 3rd through 13th, 1752, never were under the
+// 1752 reform
+fn skipped(year: i32, month: u32, day: u32, reform: Reform) -> bool {
+    reform == Reform::Y1752 && (year, month) == (1752, 9) && (3..=13).contains(&day)
+}
+
+// The Julian day number of a date, which is a Julian calendar one up to
+// September 2nd, 1752 under the 1752 reform
+fn day_number(year: i32, month: u32, day: u32, reform: Reform) -> i64 {
+    let a = (14 - month as i64) / 12;
+    let y = year as i64 + 4800 - a;
+    let m = month as i64 + 12 * a - 3;
+    let days = day as i64 + (153 * m + 2) / 5 + 365 * y + y / 4;
+
+    let julian = match reform {
+        Reform::Y1752 => (year, month, day) < (1752, 9, 14),
+        Reform::Gregorian => false,
+        Reform::Julian => true,
+    };
+    if julian {
+        days - 32083
+    } else {
+        days - y / 100 + y / 400 - 32045
+    }
+}
 
-    let is_today = |day: u32| year == today.year() && month == today.month() && day == today.day();
+// Sunday being 0
+fn first_weekday(year: i32, month: u32, reform: Reform) -> u32 {
+    ((day_number(year, month, 1, reform) + 1) % 7) as u32
+}
 
-    let last = last_day_in_month(year, month);
-    days.extend((first.day()..=last.day()).into_iter().map(|num| {
-        let fmt = format!("{:>2}", num);
+/// Lays out a month the way cal does, as the 8 lines of its grid, each
+/// `LINE_WIDTH` wide, or `JULIAN_LINE_WIDTH` with `julian`, including the
+/// two blanks that end it. The year goes in the title with `print_year`, and
+/// `today` gets highlighted in reverse video when it falls in the month, and
+/// the days of `events` get underlined.
+///
+/// Weeks start on `week_start`.
+///
+/// With `julian`, days are numbered from the start of the year, which takes
+/// a column more for each of them. The days skipped by the reform still
+/// count towards those numbers, just like with cal.
+pub fn format_month(year: i32, month: u32, print_year: bool, format: &MonthFormat) -> Vec<String> {
+    let MonthFormat {
+        today,
+        events,
+        julian,
+        week_start,
+        reform,
+    } = *format;
+    let (cell, line_width) = if julian {
+        (3, JULIAN_LINE_WIDTH)
+    } else {
+        (2, LINE_WIDTH)
+    };
 
-        if is_today(num) {
-            Style::new().reverse().paint(fmt).to_string()
-        } else {
-            fmt
-        }
-    }));
+    let offset = (first_weekday(year, month, reform) + 7 - week_start.num_days_from_sunday()) % 7;
+    let mut days: Vec<String> = (0..offset).map(|_| " ".repeat(cell)).collect();
+
+    let is_today = |day: u32| {
+        today.is_some_and(|today| {
+            year == today.year() && month == today.month() && day == today.day()
+        })
+    };
+    let is_event = |day: u32| events.iter().any(|event| falls_on(event, year, month, day));
+    let ordinal: u32 = (1..month)
+        .map(|month| days_in_month(year, month, reform))
+        .sum();
+
+    days.extend(
+        (1..=days_in_month(year, month, reform))
+            .filter(|&day| !skipped(year, month, day, reform))
+            .map(|num| {
+                let shown = if julian { ordinal + num } else { num };
+                let fmt = format!("{:>cell$}", shown);
+
+                let mut style = Style::new();
+                if is_today(num) {
+                    style = style.reverse();
+                }
+                if is_event(num) {
+                    style = style.underline();
+                }
+
+                if style.is_plain() {
+                    fmt
+                } else {
+                    style.paint(fmt).to_string()
+                }
+            }),
+    );
 
     let month_name = MONTH_NAMES[month as usize - 1];
     let mut lines = Vec::with_capacity(8);
 
     lines.push(format!(
-        "{:^20}  ",
+        "{:^width$}  ",
         if print_year {
             format!("{} {}", month_name, year)
         } else {
             month_name.to_string()
-        }
+        },
+        width = line_width - 2
     ));
 
-    lines.push("Su Mo Tu We Th Fr Sa  ".to_string());
+    let names = ["Su", "Mo", "Tu", "We", "Th", "Fr", "Sa"];
+    let weekdays: Vec<_> = (0..7)
+        .map(|day| names[(day + week_start.num_days_from_sunday() as usize) % 7])
+        .map(|day| format!("{:>cell$}", day))
+        .collect();
+    lines.push(format!("{}  ", weekdays.join(" ")));
 
     for week in days.chunks(7) {
         lines.push(format!(
             "{:width$}  ",
             week.join(" "),
-            width = LINE_WIDTH - 2
+            width = line_width - 2
         ));
     }
 
     while lines.len() < 8 {
-        lines.push(" ".repeat(LINE_WIDTH));
+        lines.push(" ".repeat(line_width));
     }
 
     lines
 }
 
+/// The year on top of its twelve months, laid out like with `format_month`,
+/// `columns` of them to a row
+pub fn format_year(year: i32, columns: usize, format: &MonthFormat) -> Vec<String> {
+    let months: Vec<Vec<String>> = (1..=12)
+        .map(|month| format_month(year, month, false, format))
+        .collect();
+
+    let mut lines = layout(&months, columns);
+    let width = lines[0].len();
+    lines.insert(0, format!("{:>width$}", year, width = width / 2 - 1));
+
+    lines
+}
+
+// Without trailing spaces nor blank lines, other than one between rows of
+// months
+fn compact(lines: &[String]) -> Vec<String> {
+    let mut compacted: Vec<String> = vec![];
+    for line in lines.iter().map(|line| line.trim_end()) {
+        if !line.is_empty() || compacted.last().is_some_and(|last| !last.is_empty()) {
+            compacted.push(line.to_string());
+        }
+    }
+    if compacted.last().is_some_and(|last| last.is_empty()) {
+        compacted.pop();
+    }
+
+    compacted
+}
+
+// Puts months side by side, `columns` to a row, with a blank line between
+// rows
+fn layout(months: &[Vec<String>], columns: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for (i, row) in months.chunks(columns).enumerate() {
+        if i > 0 {
+            lines.push(String::new());
+        }
+        lines.extend((0..row[0].len()).map(|line| {
+            row.iter()
+                .map(|month| month[line].as_str())
+                .collect::<String>()
+        }));
+    }
+
+    lines
+}
+
+// Like 2020-W01-3, with Monday being day 1 and week 1 the one with the
+// year's first Thursday
+fn format_iso_week(date: NaiveDate) -> String {
+    let week = date.iso_week();
+    format!(
+        "{:04}-W{:02}-{}",
+        week.year(),
+        week.week(),
+        date.weekday().number_from_monday()
+    )
+}
+
+// Like "10 days (1 week, 3 days)", whichever date comes first
+fn format_diff(from: NaiveDate, to: NaiveDate) -> String {
+    let plural = |n: i64, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+
+    let days = (to - from).num_days().abs();
+    format!(
+        "{} ({}, {})",
+        plural(days, "day"),
+        plural(days / 7, "week"),
+        plural(days % 7, "day")
+    )
+}
+
+// The Julian calendar date for a Gregorian one, going through its Julian day
+// number. None for a February 29th that only the Julian calendar has.
+fn julian_date(date: NaiveDate) -> Option<NaiveDate> {
+    let (year, month, day) = julian_fields(date);
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+// The year, month and day of the Julian calendar date for a Gregorian one
+fn julian_fields(date: NaiveDate) -> (i32, u32, u32) {
+    let c = date.num_days_from_ce() as i64 + 1721425 + 32082;
+    let d = (4 * c + 3) / 1461;
+    let e = c - 1461 * d / 4;
+    let m = (5 * e + 2) / 153;
+
+    let day = e - (153 * m + 2) / 5 + 1;
+    let month = m + 3 - 12 * (m / 10);
+    let year = d - 4800 + m / 10;
+    (year as i32, month as u32, day as u32)
+}
+
+// The day of the year of a date in the calendar of the reform, and the days
+// in the year, counting those skipped by the reform like -j does
+fn day_of_year(date: NaiveDate, reform: Reform) -> (u32, u32) {
+    let (year, month, day) = match reform {
+        Reform::Y1752 if date < NaiveDate::from_ymd(1752, 9, 14) => julian_fields(date),
+        Reform::Julian => julian_fields(date),
+        _ => (date.year(), date.month(), date.day()),
+    };
+    let days = |months| -> u32 {
+        (1..months)
+            .map(|month| days_in_month(year, month, reform))
+            .sum()
+    };
+
+    (days(month) + day, days(13))
+}
+
+// A YYYY-MM-DD date in the calendar of the reform, as the day it was, so
+// that days can be counted across the reform
+fn parse_date(date: &str, reform: Reform) -> MyResult<NaiveDate> {
+    let invalid = || format!("Invalid date \"{}\"", date);
+    let (year, month, day) = match date.split('-').collect::<Vec<_>>()[..] {
+        [year, month, day] if year.parse::<i32>().is_ok() => (
+            parse_year(year)?,
+            month.parse::<u32>().map_err(|_| invalid())?,
+            day.parse::<u32>().map_err(|_| invalid())?,
+        ),
+        _ => return Err(invalid().into()),
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month, reform)).contains(&day)
+        || skipped(year, month, day, reform)
+    {
+        return Err(invalid().into());
+    }
+
+    let days = day_number(year, month, day, reform) - 1721425;
+    Ok(NaiveDate::from_num_days_from_ce(days as i32))
+}
+
+fn falls_on(event: &Event, year: i32, month: u32, day: u32) -> bool {
+    event.year.is_none_or(|y| y == year) && (event.month, event.day) == (month, day)
+}
+
+// The events of the months from first to last, counted from year 0, by date
+fn print_legend(events: &[Event], first: i32, last: i32) {
+    let mut days = vec![];
+    for num in first..=last {
+        let (year, month) = (num.div_euclid(12), num.rem_euclid(12) as u32 + 1);
+        for event in events {
+            if event.month == month && event.year.is_none_or(|y| y == year) {
+                days.push((year, month, event.day, &event.description));
+            }
+        }
+    }
+    days.sort_by_key(|&(year, month, day, _)| (year, month, day));
+
+    for (year, month, day, description) in days {
+        println!("{:04}-{:02}-{:02}  {}", year, month, day, description);
+    }
+}
+
+// Lines of "YYYY-MM-DD description", or of the BSD calendar's "MM/DD" or
+// "Month DD" followed by a tab and the description, for every year. Blank
+// lines and those starting with "#" are skipped.
+fn parse_events(text: &str) -> MyResult<Vec<Event>> {
+    let mut events = vec![];
+    for line in text.lines() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || format!("Invalid event \"{}\"", line);
+        let (date, description) = match line.split_once(char::is_whitespace) {
+            Some((date, description)) if NaiveDate::from_str(date).is_ok() => (date, description),
+            _ => line.split_once('\t').ok_or_else(invalid)?,
+        };
+
+        let event = match NaiveDate::from_str(date) {
+            Ok(date) => Some((Some(date.year()), date.month(), date.day())),
+            _ => date
+                .split_once('/')
+                .or_else(|| date.split_once(' '))
+                .and_then(|(month, day)| {
+                    let month = parse_month(month.trim()).ok()?;
+                    let day = day.trim().parse().ok()?;
+                    // Any leap year will do to check the day
+                    NaiveDate::from_ymd_opt(2000, month, day).map(|_| (None, month, day))
+                }),
+        };
+
+        let (year, month, day) = event.ok_or_else(invalid)?;
+        events.push(Event {
+            year,
+            month,
+            day,
+            description: description.trim().to_string(),
+        });
+    }
+
+    Ok(events)
+}
+
 fn parse_int<T: FromStr>(val: &str) -> MyResult<T> {
     val.parse()
         .map_err(|_| format!("Invalid integer \"{}\"", val).into())
 }
 
+fn parse_span(span: &str) -> MyResult<u32> {
+    parse_int(span).and_then(|n| {
+        if n > 0 {
+            Ok(n)
+        } else {
+            Err(format!("span \"{}\" must be at least 1", span).into())
+        }
+    })
+}
+
 fn parse_year(year: &str) -> MyResult<i32> {
     parse_int(year).and_then(|y| {
         if (1..=9999).contains(&y) {
@@ -171,6 +857,48 @@ fn parse_year(year: &str) -> MyResult<i32> {
     })
 }
 
+// Either a single month or a range of them, like "6-9" or "jun-sep"
+fn parse_months(months: &str) -> MyResult<(u32, u32)> {
+    match months.split_once('-') {
+        Some((first, last)) => {
+            let (first, last) = (parse_month(first)?, parse_month(last)?);
+            if first <= last {
+                Ok((first, last))
+            } else {
+                Err(format!("Invalid month range \"{}\"", months).into())
+            }
+        }
+        None => parse_month(months).map(|month| (month, month)),
+    }
+}
+
+// Like with cal, a lone argument is a year, unless written as 2024-05 or
+// 05/2024. Out of two arguments, a first one that could be a month, like in
+// "5 2024" or "may 2024", makes them a month and its year. Anything else is
+// a list of years.
+fn parse_month_year(args: &[&str]) -> MyResult<(Option<u32>, Vec<i32>)> {
+    match args {
+        [arg] => {
+            let parts = arg
+                .split_once('-')
+                .or_else(|| arg.split_once('/').map(|(month, year)| (year, month)));
+            if let Some((year, month)) = parts {
+                return Ok((Some(parse_month(month)?), vec![parse_year(year)?]));
+            }
+        }
+        [month, year] if !matches!(parse_int::<u32>(month), Ok(m) if m > 12) => {
+            return Ok((Some(parse_month(month)?), vec![parse_year(year)?]));
+        }
+        _ => {}
+    }
+
+    let years = args
+        .iter()
+        .map(|year| parse_year(year))
+        .collect::<MyResult<_>>()?;
+    Ok((None, years))
+}
+
 fn parse_month(month: &str) -> MyResult<u32> {
     match parse_int(month) {
         // A number was passed in as a string
@@ -211,8 +939,14 @@ fn parse_month(month: &str) -> MyResult<u32> {
 
 #[cfg(test)]
 mod tests {
-    use super::{format_month, parse_int, parse_month, parse_year};
-    use chrono::NaiveDate;
+    use super::{
+        compact, day_of_year, days_in_month, first_weekday, format_diff, format_iso_week,
+        format_month, format_year, julian_date, locale_week_start, parse_date, parse_events,
+        parse_int, parse_month, parse_month_year, parse_months, parse_span, parse_year, Event,
+        MonthFormat, Reform,
+    };
+    use chrono::{NaiveDate, Weekday};
+    use std::fs;
 
     #[test]
     fn test_parse_int() {
@@ -232,6 +966,20 @@ mod tests {
         assert_eq!(res.unwrap_err().to_string(), "Invalid integer \"foo\"");
     }
 
+    #[test]
+    fn test_parse_span() {
+        assert_eq!(parse_span("6").unwrap(), 6);
+
+        let res = parse_span("0");
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "span \"0\" must be at least 1"
+        );
+
+        let res = parse_span("-1");
+        assert_eq!(res.unwrap_err().to_string(), "Invalid integer \"-1\"");
+    }
+
     #[test]
     fn test_parse_year() {
         let res = parse_year("1");
@@ -294,6 +1042,176 @@ mod tests {
         assert_eq!(res.unwrap_err().to_string(), "Invalid month \"foo\"");
     }
 
+    #[test]
+    fn test_parse_month_year() {
+        assert_eq!(parse_month_year(&["2024"]).unwrap(), (None, vec![2024]));
+        // A lone number is a year, even when it could be a month
+        assert_eq!(parse_month_year(&["5"]).unwrap(), (None, vec![5]));
+        assert_eq!(
+            parse_month_year(&["2024-05"]).unwrap(),
+            (Some(5), vec![2024])
+        );
+        assert_eq!(
+            parse_month_year(&["05/2024"]).unwrap(),
+            (Some(5), vec![2024])
+        );
+        assert_eq!(
+            parse_month_year(&["5", "2024"]).unwrap(),
+            (Some(5), vec![2024])
+        );
+        assert_eq!(
+            parse_month_year(&["may", "2024"]).unwrap(),
+            (Some(5), vec![2024])
+        );
+        assert_eq!(
+            parse_month_year(&["2024", "2025"]).unwrap(),
+            (None, vec![2024, 2025])
+        );
+        assert_eq!(
+            parse_month_year(&["5", "2024", "2025"]).unwrap(),
+            (None, vec![5, 2024, 2025])
+        );
+
+        let res = parse_month_year(&["2024-13"]);
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "month \"13\" not in the range 1 through 12"
+        );
+
+        let res = parse_month_year(&["foo", "2024"]);
+        assert_eq!(res.unwrap_err().to_string(), "Invalid month \"foo\"");
+    }
+
+    #[test]
+    fn test_parse_months() {
+        assert_eq!(parse_months("6").unwrap(), (6, 6));
+        assert_eq!(parse_months("6-9").unwrap(), (6, 9));
+        assert_eq!(parse_months("jun-sep").unwrap(), (6, 9));
+        assert_eq!(parse_months("3-3").unwrap(), (3, 3));
+
+        let res = parse_months("9-6");
+        assert_eq!(res.unwrap_err().to_string(), "Invalid month range \"9-6\"");
+
+        let res = parse_months("6-13");
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "month \"13\" not in the range 1 through 12"
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let res = parse_date("2020-02-29", Reform::Gregorian);
+        assert_eq!(res.unwrap(), NaiveDate::from_ymd(2020, 2, 29));
+
+        let res = parse_date("2021-02-29", Reform::Gregorian);
+        assert_eq!(res.unwrap_err().to_string(), "Invalid date \"2021-02-29\"");
+
+        let res = parse_date("foo", Reform::Gregorian);
+        assert_eq!(res.unwrap_err().to_string(), "Invalid date \"foo\"");
+
+        let res = parse_date("0000-01-01", Reform::Gregorian);
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "year \"0000\" not in the range 1 through 9999"
+        );
+
+        // Before the reform, dates are Julian ones, some days never were,
+        // and 1700 was a leap year
+        let res = parse_date("1752-09-02", Reform::Y1752);
+        assert_eq!(res.unwrap(), NaiveDate::from_ymd(1752, 9, 13));
+        let res = parse_date("1752-09-14", Reform::Y1752);
+        assert_eq!(res.unwrap(), NaiveDate::from_ymd(1752, 9, 14));
+        let res = parse_date("1752-09-05", Reform::Y1752);
+        assert_eq!(res.unwrap_err().to_string(), "Invalid date \"1752-09-05\"");
+        let res = parse_date("1700-02-29", Reform::Y1752);
+        assert_eq!(res.unwrap(), NaiveDate::from_ymd(1700, 3, 11));
+        let res = parse_date("1700-02-29", Reform::Gregorian);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_format_iso_week() {
+        // The first days of a year can belong to the last week of the one before
+        assert_eq!(
+            format_iso_week(NaiveDate::from_ymd(2021, 1, 1)),
+            "2020-W53-5"
+        );
+        assert_eq!(
+            format_iso_week(NaiveDate::from_ymd(2020, 1, 1)),
+            "2020-W01-3"
+        );
+        assert_eq!(
+            format_iso_week(NaiveDate::from_ymd(2019, 12, 30)),
+            "2020-W01-1"
+        );
+        assert_eq!(
+            format_iso_week(NaiveDate::from_ymd(2020, 12, 31)),
+            "2020-W53-4"
+        );
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let text = "# Holidays\n\n2020-02-14 Valentine's Day\n12/25\tChristmas\nJul 4\t Independence Day\n";
+        let event = |year, month, day, description: &str| Event {
+            year,
+            month,
+            day,
+            description: description.to_string(),
+        };
+        assert_eq!(
+            parse_events(text).unwrap(),
+            vec![
+                event(Some(2020), 2, 14, "Valentine's Day"),
+                event(None, 12, 25, "Christmas"),
+                event(None, 7, 4, "Independence Day"),
+            ]
+        );
+
+        let res = parse_events("02/30\tNever");
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Invalid event \"02/30\tNever\""
+        );
+
+        let res = parse_events("tomorrow");
+        assert_eq!(res.unwrap_err().to_string(), "Invalid event \"tomorrow\"");
+    }
+
+    #[test]
+    fn test_format_diff() {
+        let (from, to) = (
+            NaiveDate::from_ymd(2020, 1, 1),
+            NaiveDate::from_ymd(2021, 1, 1),
+        );
+        assert_eq!(format_diff(from, to), "366 days (52 weeks, 2 days)");
+        assert_eq!(format_diff(to, from), "366 days (52 weeks, 2 days)");
+
+        let (from, to) = (
+            NaiveDate::from_ymd(2020, 1, 1),
+            NaiveDate::from_ymd(2020, 1, 9),
+        );
+        assert_eq!(format_diff(from, to), "8 days (1 week, 1 day)");
+        assert_eq!(format_diff(from, from), "0 days (0 weeks, 0 days)");
+    }
+
+    #[test]
+    fn test_day_of_year() {
+        let date = NaiveDate::from_ymd(2020, 2, 29);
+        assert_eq!(day_of_year(date, Reform::Y1752), (60, 366));
+
+        let date = NaiveDate::from_ymd(2021, 12, 31);
+        assert_eq!(day_of_year(date, Reform::Y1752), (365, 365));
+
+        // 1700 was a leap year before the reform, and March 1st then was
+        // March 12th in Gregorian terms
+        let date = NaiveDate::from_ymd(1700, 3, 12);
+        assert_eq!(day_of_year(date, Reform::Y1752), (61, 366));
+        let date = NaiveDate::from_ymd(1700, 3, 1);
+        assert_eq!(day_of_year(date, Reform::Gregorian), (60, 365));
+    }
+
     #[test]
     fn test_format_month() {
         let today = NaiveDate::from_ymd(0, 1, 1);
@@ -307,7 +1225,18 @@ mod tests {
             "23 24 25 26 27 28 29  ",
             "                      ",
         ];
-        assert_eq!(format_month(2020, 2, true, today), leap_february);
+        assert_eq!(
+            format_month(
+                2020,
+                2,
+                true,
+                &MonthFormat {
+                    today: Some(today),
+                    ..MonthFormat::default()
+                }
+            ),
+            leap_february
+        );
 
         let may = vec![
             "        May           ",
@@ -319,7 +1248,18 @@ mod tests {
             "24 25 26 27 28 29 30  ",
             "31                    ",
         ];
-        assert_eq!(format_month(2020, 5, false, today), may);
+        assert_eq!(
+            format_month(
+                2020,
+                5,
+                false,
+                &MonthFormat {
+                    today: Some(today),
+                    ..MonthFormat::default()
+                }
+            ),
+            may
+        );
 
         let april_hl = vec![
             "     April 2021       ",
@@ -331,7 +1271,175 @@ mod tests {
             "25 26 27 28 29 30     ",
             "                      ",
         ];
+        let julian_february = vec![
+            "       February 2020         ",
+            " Su  Mo  Tu  We  Th  Fr  Sa  ",
+            "                         32  ",
+            " 33  34  35  36  37  38  39  ",
+            " 40  41  42  43  44  45  46  ",
+            " 47  48  49  50  51  52  53  ",
+            " 54  55  56  57  58  59  60  ",
+            "                             ",
+        ];
+        assert_eq!(
+            format_month(
+                2020,
+                2,
+                true,
+                &MonthFormat {
+                    julian: true,
+                    ..MonthFormat::default()
+                }
+            ),
+            julian_february
+        );
+
+        let valentines = Event {
+            year: None,
+            month: 2,
+            day: 14,
+            description: "Valentine's Day".to_string(),
+        };
+        let february_events = format_month(
+            2020,
+            2,
+            true,
+            &MonthFormat {
+                events: &[valentines],
+                ..MonthFormat::default()
+            },
+        );
+        assert_eq!(
+            february_events[4],
+            " 9 10 11 12 13 \u{1b}[4m14\u{1b}[0m 15  "
+        );
+
         let today = NaiveDate::from_ymd(2021, 4, 7);
-        assert_eq!(format_month(2021, 4, true, today), april_hl);
+        assert_eq!(
+            format_month(
+                2021,
+                4,
+                true,
+                &MonthFormat {
+                    today: Some(today),
+                    ..MonthFormat::default()
+                }
+            ),
+            april_hl
+        );
+    }
+
+    #[test]
+    fn test_format_year() {
+        let today = NaiveDate::from_ymd(0, 1, 1);
+        let expected = fs::read_to_string("tests/expected/2020.txt").unwrap();
+        assert_eq!(
+            format_year(
+                2020,
+                3,
+                &MonthFormat {
+                    today: Some(today),
+                    ..MonthFormat::default()
+                }
+            ),
+            expected.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_locale_week_start() {
+        assert_eq!(locale_week_start("en_US.UTF-8"), Weekday::Sun);
+        assert_eq!(locale_week_start("pt_BR"), Weekday::Sun);
+        assert_eq!(locale_week_start("en_GB.UTF-8"), Weekday::Mon);
+        assert_eq!(locale_week_start("de_DE@euro"), Weekday::Mon);
+        assert_eq!(locale_week_start("C.UTF-8"), Weekday::Sun);
+        assert_eq!(locale_week_start(""), Weekday::Sun);
+    }
+
+    #[test]
+    fn test_week_start() {
+        let monday_february = vec![
+            "   February 2020      ",
+            "Mo Tu We Th Fr Sa Su  ",
+            "                1  2  ",
+            " 3  4  5  6  7  8  9  ",
+            "10 11 12 13 14 15 16  ",
+            "17 18 19 20 21 22 23  ",
+            "24 25 26 27 28 29     ",
+            "                      ",
+        ];
+        assert_eq!(
+            format_month(
+                2020,
+                2,
+                true,
+                &MonthFormat {
+                    week_start: Weekday::Mon,
+                    ..MonthFormat::default()
+                }
+            ),
+            monday_february
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        let lines: Vec<String> = ["  May  ", "31    ", "      ", "", "  June ", "      "]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(compact(&lines), vec!["  May", "31", "", "  June"]);
+    }
+
+    #[test]
+    fn test_reform() {
+        // 1700 was a leap year only for the Julian calendar
+        assert_eq!(days_in_month(1700, 2, Reform::Y1752), 29);
+        assert_eq!(days_in_month(1700, 2, Reform::Gregorian), 28);
+        assert_eq!(days_in_month(1900, 2, Reform::Y1752), 28);
+
+        // September 2nd, a Wednesday, was followed by Thursday the 14th
+        assert_eq!(first_weekday(1752, 9, Reform::Y1752), 2);
+        assert_eq!(first_weekday(1752, 9, Reform::Gregorian), 5);
+        assert_eq!(first_weekday(1752, 10, Reform::Y1752), 0);
+        assert_eq!(first_weekday(2020, 2, Reform::Y1752), 6);
+
+        // The Julian calendar keeps every fourth year a leap year, and was
+        // thirteen days behind in 2020
+        assert_eq!(days_in_month(1900, 2, Reform::Julian), 29);
+        assert_eq!(first_weekday(2020, 2, Reform::Julian), 5);
+        assert_eq!(
+            julian_date(NaiveDate::from_ymd(2020, 2, 14)),
+            Some(NaiveDate::from_ymd(2020, 2, 1))
+        );
+        assert_eq!(
+            julian_date(NaiveDate::from_ymd(1752, 9, 14)),
+            Some(NaiveDate::from_ymd(1752, 9, 3))
+        );
+        assert_eq!(julian_date(NaiveDate::from_ymd(1900, 3, 13)), None);
+
+        let today = NaiveDate::from_ymd(0, 1, 1);
+        let september = vec![
+            "   September 1752     ",
+            "Su Mo Tu We Th Fr Sa  ",
+            "       1  2 14 15 16  ",
+            "17 18 19 20 21 22 23  ",
+            "24 25 26 27 28 29 30  ",
+            "                      ",
+            "                      ",
+            "                      ",
+        ];
+        assert_eq!(
+            format_month(
+                1752,
+                9,
+                true,
+                &MonthFormat {
+                    today: Some(today),
+                    ..MonthFormat::default()
+                }
+            ),
+            september
+        );
     }
 }
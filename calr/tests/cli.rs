@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use chrono::{Datelike, Local};
 use predicates::prelude::*;
 use std::{error::Error, fs};
 
@@ -6,10 +7,21 @@ type TestResult = Result<(), Box<dyn Error>>;
 
 const PRG: &str = "calr";
 
+// --------------------------------------------------
+// calr with no say from the locale or CALR_FIRST_WEEKDAY of whoever runs
+// the tests, so weeks start on Sunday
+fn calr() -> Result<Command, Box<dyn Error>> {
+    let mut cmd = Command::cargo_bin(PRG)?;
+    for name in ["CALR_FIRST_WEEKDAY", "LC_ALL", "LC_TIME", "LANG"] {
+        cmd.env_remove(name);
+    }
+    Ok(cmd)
+}
+
 // --------------------------------------------------
 #[test]
 fn dies_year_0() -> TestResult {
-    Command::cargo_bin(PRG)?
+    calr()?
         .arg("0")
         .assert()
         .failure()
@@ -20,7 +32,7 @@ fn dies_year_0() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn dies_year_13() -> TestResult {
-    Command::cargo_bin(PRG)?
+    calr()?
         .arg("10000")
         .assert()
         .failure()
@@ -31,7 +43,7 @@ fn dies_year_13() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn dies_invalid_year() -> TestResult {
-    Command::cargo_bin(PRG)?
+    calr()?
         .arg("foo")
         .assert()
         .failure()
@@ -42,8 +54,8 @@ fn dies_invalid_year() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn dies_month_0() -> TestResult {
-    Command::cargo_bin(PRG)?
-        .args(&["-m", "0"])
+    calr()?
+        .args(["-m", "0"])
         .assert()
         .failure()
         .stderr("month \"0\" not in the range 1 through 12\n");
@@ -53,8 +65,8 @@ fn dies_month_0() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn dies_month_13() -> TestResult {
-    Command::cargo_bin(PRG)?
-        .args(&["-m", "13"])
+    calr()?
+        .args(["-m", "13"])
         .assert()
         .failure()
         .stderr("month \"13\" not in the range 1 through 12\n");
@@ -64,8 +76,8 @@ fn dies_month_13() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn dies_invalid_month() -> TestResult {
-    Command::cargo_bin(PRG)?
-        .args(&["-m", "foo"])
+    calr()?
+        .args(["-m", "foo"])
         .assert()
         .failure()
         .stderr("Invalid month \"foo\"\n");
@@ -76,8 +88,8 @@ fn dies_invalid_month() -> TestResult {
 #[test]
 fn dies_y_and_month() -> TestResult {
     let expected = "The argument '-m <MONTH>' cannot be used with '--year'";
-    Command::cargo_bin(PRG)?
-        .args(&["-m", "1", "-y"])
+    calr()?
+        .args(["-m", "1", "-y"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -87,9 +99,9 @@ fn dies_y_and_month() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn dies_y_and_year() -> TestResult {
-    let expected = "The argument '<YEAR>' cannot be used with '--year'";
-    Command::cargo_bin(PRG)?
-        .args(&["-y", "2000"])
+    let expected = "The argument '<YEAR>...' cannot be used with '--year'";
+    calr()?
+        .args(["-y", "2000"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -115,8 +127,8 @@ fn month_num() -> TestResult {
     ];
 
     for (num, month) in expected {
-        Command::cargo_bin(PRG)?
-            .args(&["-m", num])
+        calr()?
+            .args(["-m", num])
             .assert()
             .success()
             .stdout(predicates::str::contains(month.to_string()));
@@ -142,8 +154,8 @@ fn partial_month() -> TestResult {
     ];
 
     for (arg, month) in expected {
-        Command::cargo_bin(PRG)?
-            .args(&["-m", arg])
+        calr()?
+            .args(["-m", arg])
             .assert()
             .success()
             .stdout(predicates::str::contains(month.to_string()));
@@ -154,18 +166,14 @@ fn partial_month() -> TestResult {
 // --------------------------------------------------
 fn run(args: &[&str], expected_file: &str) -> TestResult {
     let expected = fs::read_to_string(expected_file)?;
-    Command::cargo_bin(PRG)?
-        .args(args)
-        .assert()
-        .success()
-        .stdout(expected);
+    calr()?.args(args).assert().success().stdout(expected);
     Ok(())
 }
 
 // --------------------------------------------------
 #[test]
 fn default_one_month() -> TestResult {
-    let cmd = Command::cargo_bin(PRG)?.assert().success();
+    let cmd = calr()?.assert().success();
     let out = cmd.get_output();
     let stdout = String::from_utf8(out.stdout.clone())?;
     let lines: Vec<_> = stdout.split("\n").collect();
@@ -201,9 +209,540 @@ fn test_2020() -> TestResult {
 // --------------------------------------------------
 #[test]
 fn year() -> TestResult {
-    let cmd = Command::cargo_bin(PRG)?.arg("-y").assert().success();
+    let cmd = calr()?.arg("-y").assert().success();
     let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
     let lines: Vec<&str> = stdout.split("\n").collect();
     assert_eq!(lines.len(), 37);
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn julian_2_2020() -> TestResult {
+    run(&["-j", "-m", "2", "2020"], "tests/expected/j-2-2020.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn julian_year() -> TestResult {
+    // Two months to a row makes for six rows
+    let cmd = calr()?.args(["-j", "2020"]).assert().success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.split('\n').collect();
+    assert_eq!(lines.len(), 55);
+    assert!(stdout.contains(" 336 337 338 339 340  "));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn test_9_1752() -> TestResult {
+    run(&["-m", "9", "1752"], "tests/expected/9-1752.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn reform_gregorian() -> TestResult {
+    for reform in ["gregorian", "iso"] {
+        calr()?
+            .args(["--reform", reform, "-m", "9", "1752"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("10 11 12 13 14 15 16"));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn months_before_and_after() -> TestResult {
+    let cmd = calr()?
+        .args(["-m", "1", "2021", "-B", "2", "-A", "1"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 17);
+    assert_eq!(
+        lines[0],
+        "   November 2020         December 2020          January 2021      "
+    );
+    assert_eq!(lines[9], "   February 2021      ");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_after() -> TestResult {
+    calr()?
+        .args(["-m", "1", "-A", "foo"])
+        .assert()
+        .failure()
+        .stderr("Invalid integer \"foo\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn month_range() -> TestResult {
+    let cmd = calr()?.args(["-m", "6-9", "2024"]).assert().success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 17);
+    assert_eq!(
+        lines[0],
+        "     June 2024             July 2024            August 2024       "
+    );
+    assert_eq!(lines[9], "   September 2024     ");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_backwards_month_range() -> TestResult {
+    calr()?
+        .args(["-m", "9-6", "2024"])
+        .assert()
+        .failure()
+        .stderr("Invalid month range \"9-6\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color() -> TestResult {
+    // Not a terminal, so no highlighting unless asked for
+    for args in [&[][..], &["--color", "auto"], &["--color", "never"]] {
+        calr()?
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("\u{1b}[7m").not());
+    }
+
+    calr()?
+        .args(["--color", "always"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}[7m"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn columns() -> TestResult {
+    let weekdays = "Su Mo Tu We Th Fr Sa  ";
+    for columns in ["2", "3", "4", "6"] {
+        let row = weekdays.repeat(columns.parse()?);
+        calr()?
+            .args(["--columns", columns, "2020"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("\n{}\n", row)));
+    }
+
+    // Off a terminal, the layout is the usual one
+    calr()?
+        .arg("2020")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "\n{}\n",
+            weekdays.repeat(3)
+        )));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_columns() -> TestResult {
+    calr()?
+        .args(["--columns", "5"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "isn't a valid value for '--columns <N>'",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn iso_week() -> TestResult {
+    calr()?
+        .args(["--iso-week", "2021-01-01"])
+        .assert()
+        .success()
+        .stdout("2020-W53-5\n");
+
+    let today = Local::today();
+    let week = today.iso_week();
+    calr()?.arg("--iso-week").assert().success().stdout(format!(
+        "{:04}-W{:02}-{}\n",
+        week.year(),
+        week.week(),
+        today.weekday().number_from_monday()
+    ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_iso_week() -> TestResult {
+    calr()?
+        .args(["--iso-week", "2021-02-29"])
+        .assert()
+        .failure()
+        .stderr("Invalid date \"2021-02-29\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_years() -> TestResult {
+    let expected = fs::read_to_string("tests/expected/2020.txt")?;
+    calr()?
+        .args(["2020", "2020"])
+        .assert()
+        .success()
+        .stdout(expected.repeat(2));
+
+    let expected = fs::read_to_string("tests/expected/2-2020.txt")?;
+    calr()?
+        .args(["-m", "2", "2020", "2020"])
+        .assert()
+        .success()
+        .stdout(expected.repeat(2));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_second_year() -> TestResult {
+    calr()?
+        .args(["2020", "0"])
+        .assert()
+        .failure()
+        .stderr("year \"0\" not in the range 1 through 9999\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn events_legend() -> TestResult {
+    let expected = fs::read_to_string("tests/expected/2-2020.txt")?;
+    calr()?
+        .args([
+            "--events",
+            "tests/inputs/events.txt",
+            "--legend",
+            "-m",
+            "2",
+            "2020",
+        ])
+        .assert()
+        .success()
+        .stdout(expected + "2020-02-02  Groundhog Day\n2020-02-14  Valentine's Day\n");
+
+    // Only the events of the year shown
+    calr()?
+        .args(["--events", "tests/inputs/events.txt", "--legend", "2021"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "2021-02-02  Groundhog Day\n2021-04-01  April Fools' Day\n",
+        ))
+        .stdout(predicate::str::contains("Valentine").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn events_marked() -> TestResult {
+    calr()?
+        .args([
+            "--events",
+            "tests/inputs/events.txt",
+            "--color",
+            "always",
+            "-m",
+            "2",
+            "2020",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\u{1b}[4m 2\u{1b}[0m"))
+        .stdout(predicate::str::contains("\u{1b}[4m14\u{1b}[0m"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_events() -> TestResult {
+    calr()?
+        .args(["--events", "tests/cli.rs"])
+        .assert()
+        .failure()
+        .stderr("tests/cli.rs: Invalid event \"use assert_cmd::Command;\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn month_year_args() -> TestResult {
+    for args in [
+        &["2020-02"][..],
+        &["02/2020"],
+        &["2", "2020"],
+        &["feb", "2020"],
+    ] {
+        run(args, "tests/expected/2-2020.txt")?;
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_month_twice() -> TestResult {
+    calr()?
+        .args(["-m", "4", "2020-02"])
+        .assert()
+        .failure()
+        .stderr("The month was given both with -m and as an argument\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn week_start() -> TestResult {
+    let monday = "\nMo Tu We Th Fr Sa Su  \n                1  2  \n";
+    calr()?
+        .args(["-M", "2", "2020"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(monday));
+
+    calr()?
+        .env("CALR_FIRST_WEEKDAY", "monday")
+        .args(["2", "2020"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(monday));
+
+    calr()?
+        .env("LANG", "en_GB.UTF-8")
+        .args(["2", "2020"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(monday));
+
+    // Flags win over the environment
+    let expected = fs::read_to_string("tests/expected/2-2020.txt")?;
+    calr()?
+        .env("CALR_FIRST_WEEKDAY", "monday")
+        .args(["--sunday", "2", "2020"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn monday_locale() -> TestResult {
+    // LC_ALL wins over LC_TIME, which wins over LANG
+    let monday = "\nMo Tu We Th Fr Sa Su  \n                1  2  \n";
+    calr()?
+        .env("LC_ALL", "de_DE.UTF-8")
+        .env("LANG", "en_US.UTF-8")
+        .args(["2", "2020"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(monday));
+
+    let expected = fs::read_to_string("tests/expected/2-2020.txt")?;
+    calr()?
+        .env("LC_TIME", "en_US.UTF-8")
+        .env("LANG", "de_DE.UTF-8")
+        .args(["2", "2020"])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_first_weekday() -> TestResult {
+    calr()?
+        .env("CALR_FIRST_WEEKDAY", "someday")
+        .assert()
+        .failure()
+        .stderr("Invalid CALR_FIRST_WEEKDAY \"someday\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn span() -> TestResult {
+    let cmd = calr()?
+        .args(["-m", "sep", "--span", "5", "2024"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 17);
+    assert_eq!(
+        lines[0],
+        "   September 2024         October 2024         November 2024      "
+    );
+    assert_eq!(lines[9], "   December 2024          January 2025      ");
+
+    // No months past the year 9999
+    let cmd = calr()?
+        .args(["-m", "dec", "--span", "3", "9999"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert_eq!(stdout.lines().count(), 8);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_span() -> TestResult {
+    calr()?
+        .args(["-m", "1", "--span", "0"])
+        .assert()
+        .failure()
+        .stderr("span \"0\" must be at least 1\n");
+
+    calr()?
+        .args(["-m", "6-9", "--span", "2"])
+        .assert()
+        .failure()
+        .stderr("--span cannot be used with a month range\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn compact() -> TestResult {
+    let expected = "      February
+Su Mo Tu We Th Fr Sa
+                   1
+ 2  3  4  5  6  7  8
+ 9 10 11 12 13 14 15
+16 17 18 19 20 21 22
+23 24 25 26 27 28 29
+";
+    calr()?
+        .args(["--compact", "-m", "2", "2020"])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    let cmd = calr()?.args(["--compact", "2020"]).assert().success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    assert!(stdout.lines().all(|line| !line.ends_with(' ')));
+    assert!(!stdout.contains("\n\n\n"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn doy() -> TestResult {
+    calr()?
+        .args(["--doy", "2020-02-29"])
+        .assert()
+        .success()
+        .stdout("Day 60 of 366, 306 remaining\n");
+
+    let today = Local::today();
+    calr()?
+        .arg("--doy")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "Day {} of ",
+            today.ordinal()
+        )));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_doy() -> TestResult {
+    calr()?
+        .args(["--doy", "2021-02-29"])
+        .assert()
+        .failure()
+        .stderr("Invalid date \"2021-02-29\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn julian_calendar() -> TestResult {
+    // February 1st, 2020 was Valentine's Day, a Friday, in Gregorian terms
+    calr()?
+        .args(["--julian-calendar", "-m", "2", "2020"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\n                1  2  \n"));
+
+    // And 1900 was still a leap year
+    calr()?
+        .args(["--julian-calendar", "-m", "2", "1900"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(" 29 "));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn diff() -> TestResult {
+    calr()?
+        .args(["--diff", "2024-01-01", "2024-12-25"])
+        .assert()
+        .success()
+        .stdout("359 days (51 weeks, 2 days)\n");
+
+    calr()?
+        .args(["--diff", "2024-01-08", "2024-01-01"])
+        .assert()
+        .success()
+        .stdout("7 days (1 week, 0 days)\n");
+
+    // The day after September 2nd, 1752 was the 14th
+    calr()?
+        .args(["--reform", "1752", "--diff", "1752-09-02", "1752-09-14"])
+        .assert()
+        .success()
+        .stdout("1 day (0 weeks, 1 day)\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_diff() -> TestResult {
+    calr()?
+        .args(["--diff", "2024-01-01", "2023-02-29"])
+        .assert()
+        .failure()
+        .stderr("Invalid date \"2023-02-29\"\n");
+
+    calr()?
+        .args(["--diff", "0000-12-31", "2024-01-01"])
+        .assert()
+        .failure()
+        .stderr("year \"0000\" not in the range 1 through 9999\n");
+
+    calr()?
+        .args(["--reform", "1752", "--doy", "1752-09-05"])
+        .assert()
+        .failure()
+        .stderr("Invalid date \"1752-09-05\"\n");
+    Ok(())
+}
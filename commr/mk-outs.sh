@@ -46,3 +46,9 @@ comm -12     $IN_DIR/file1.txt $IN_DIR/file2.txt | sed "s/\t/:/g" > $OUT_DIR/fil
 comm -23     $IN_DIR/file1.txt $IN_DIR/file2.txt | sed "s/\t/:/g" > $OUT_DIR/file1_file2.23.delim.out
 comm -13     $IN_DIR/file1.txt $IN_DIR/file2.txt | sed "s/\t/:/g" > $OUT_DIR/file1_file2.13.delim.out
 comm -123    $IN_DIR/file1.txt $IN_DIR/file2.txt | sed "s/\t/:/g" > $OUT_DIR/file1_file2.123.delim.out
+
+comm -i      $IN_DIR/file1.txt $IN_DIR/upper.txt > $OUT_DIR/file1_upper.i.out
+
+comm -12     $IN_DIR/file1.txt $IN_DIR/file2.txt > $OUT_DIR/file1_file2.intersect.out
+comm         $IN_DIR/file1.txt $IN_DIR/file2.txt | sed "s/^\t*//" > $OUT_DIR/file1_file2.union.out
+comm -23     $IN_DIR/file1.txt $IN_DIR/file2.txt > $OUT_DIR/file1_file2.diff.out
@@ -1,21 +1,85 @@
-use crate::Column::*;
-use clap::{App, Arg};
-use std::cmp::Ordering::*;
+use clap::{App, Arg, ErrorKind};
+use std::cell::Cell;
 use std::error::Error;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::iter;
+use std::process;
+use std::time::{Duration, Instant};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
 #[derive(Debug)]
 pub struct Config {
-    file1: String,
-    file2: String,
-    show_col1: bool,
-    show_col2: bool,
-    show_col3: bool,
-    insensitive: bool,
+    files: Vec<String>,
+    show: Vec<bool>,
     delimiter: String,
+    flat: bool,
+    format: Format,
+    count: bool,
+    status: bool,
+    progress: bool,
+    options: Options,
+}
+
+/// How lines are compared, for both the command and `classify`
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Compare lines ignoring case, like `-i`
+    pub insensitive: bool,
+    /// Compare only the fields from the first to the second, counting from
+    /// 1, like `-k`
+    pub key: Option<(usize, usize)>,
+    /// What splits fields for `key`, rather than runs of whitespace, like
+    /// `-t`
+    pub separator: Option<String>,
+    /// Skip blank lines, like `--ignore-blank-lines`
+    pub ignore_blank: bool,
+    /// Ignore whitespace at the end of lines, like
+    /// `--ignore-trailing-space`
+    pub ignore_trailing: bool,
+}
+
+/// Where `classify` puts a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    /// Only in the first input
+    Column1,
+    /// Only in the second input
+    Column2,
+    /// In both inputs
+    Column3,
+}
+
+// The flags suppressing each column, with columns 4 through 7 only there
+// for three files
+const SUPPRESS: [&str; 7] = [
+    "suppress_col1",
+    "suppress_col2",
+    "suppress_col3",
+    "suppress_col4",
+    "suppress_col5",
+    "suppress_col6",
+    "suppress_col7",
+];
+
+// What the lines of each column have in common, for two or three files
+const TAGS2: [&str; 3] = ["file1", "file2", "both"];
+const TAGS3: [&str; 7] = [
+    "file1",
+    "file2",
+    "file3",
+    "file1,file2",
+    "file1,file3",
+    "file2,file3",
+    "all",
+];
+
+#[derive(Debug, PartialEq)]
+enum Format {
+    Columns,
+    Tsv,
+    Json,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -37,6 +101,24 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(true)
                 .required(true),
         )
+        .arg(
+            Arg::with_name("file3")
+                .value_name("FILE3")
+                .help("Input file 3, for seven columns")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("ignore_blank")
+                .short("B")
+                .long("ignore-blank-lines")
+                .help("Skip lines with nothing but whitespace"),
+        )
+        .arg(
+            Arg::with_name("ignore_trailing")
+                .short("Z")
+                .long("ignore-trailing-space")
+                .help("Compare lines without their trailing whitespace, CRs included"),
+        )
         .arg(
             Arg::with_name("insensitive")
                 .short("i")
@@ -57,6 +139,90 @@ pub fn get_args() -> MyResult<Config> {
                 .short("3")
                 .help("Suppress printing of column 3"),
         )
+        .arg(
+            Arg::with_name("suppress_col4")
+                .short("4")
+                .help("Suppress printing of column 4")
+                .requires("file3"),
+        )
+        .arg(
+            Arg::with_name("suppress_col5")
+                .short("5")
+                .help("Suppress printing of column 5")
+                .requires("file3"),
+        )
+        .arg(
+            Arg::with_name("suppress_col6")
+                .short("6")
+                .help("Suppress printing of column 6")
+                .requires("file3"),
+        )
+        .arg(
+            Arg::with_name("suppress_col7")
+                .short("7")
+                .help("Suppress printing of column 7")
+                .requires("file3"),
+        )
+        .arg(
+            Arg::with_name("intersect")
+                .long("intersect")
+                .help("Print only lines in every file, like -12")
+                .conflicts_with_all(&SUPPRESS),
+        )
+        .arg(
+            Arg::with_name("union")
+                .long("union")
+                .help("Print every line once, without indentation")
+                .conflicts_with("intersect")
+                .conflicts_with_all(&SUPPRESS),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .long("diff")
+                .help("Print only lines unique to file 1, like -23")
+                .conflicts_with_all(&["intersect", "union"])
+                .conflicts_with_all(&SUPPRESS),
+        )
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, with tsv and json tagging each line")
+                .possible_values(&["columns", "tsv", "json"])
+                .default_value("columns"),
+        )
+        .arg(
+            Arg::with_name("key")
+                .value_name("N[,M]")
+                .short("k")
+                .long("key")
+                .help("Compare only on field N, or fields N through M")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("separator")
+                .value_name("SEP")
+                .short("t")
+                .long("field-separator")
+                .help("Field separator, instead of runs of whitespace")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count")
+                .long("count")
+                .help("Print how many lines each column has instead"),
+        )
+        .arg(
+            Arg::with_name("status")
+                .long("status")
+                .help("Print nothing, exit with 0 when no line is unique, 1 otherwise")
+                .conflicts_with("count"),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .help("Report on STDERR how much of each input has been read"),
+        )
         .arg(
             Arg::with_name("delimiter")
                 .value_name("DELIM")
@@ -66,123 +232,400 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(true)
                 .default_value("\t"),
         )
-        .get_matches();
+        .get_matches_safe()
+        .unwrap_or_else(|e| match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            // Usage errors mustn't pass for files differing with --status
+            _ => {
+                eprintln!("{}", e.message);
+                process::exit(2);
+            }
+        });
 
-    let file1 = matches.value_of("file1").unwrap().to_string();
-    let file2 = matches.value_of("file2").unwrap().to_string();
-    let show_col1 = !matches.is_present("suppress_col1");
-    let show_col2 = !matches.is_present("suppress_col2");
-    let show_col3 = !matches.is_present("suppress_col3");
-    let insensitive = matches.is_present("insensitive");
+    let files: Vec<String> = ["file1", "file2", "file3"]
+        .iter()
+        .filter_map(|name| matches.value_of(name).map(String::from))
+        .collect();
+    let intersect = matches.is_present("intersect");
+    let union = matches.is_present("union");
+    let diff = matches.is_present("diff");
+    let columns = if files.len() == 3 { 7 } else { 3 };
+    // The last column has the lines in every file
+    let show = (0..columns)
+        .map(|col| {
+            if intersect {
+                col == columns - 1
+            } else if diff {
+                col == 0
+            } else {
+                union || !matches.is_present(SUPPRESS[col])
+            }
+        })
+        .collect();
     let delimiter = matches.value_of("delimiter").unwrap().to_string();
+    let key = matches.value_of("key").map(parse_key).transpose()?;
 
     Ok(Config {
-        file1,
-        file2,
-        show_col1,
-        show_col2,
-        show_col3,
-        insensitive,
+        files,
+        show,
         delimiter,
+        // Sets have no columns
+        flat: intersect || union || diff,
+        format: match matches.value_of("format") {
+            Some("tsv") => Format::Tsv,
+            Some("json") => Format::Json,
+            _ => Format::Columns,
+        },
+        count: matches.is_present("count"),
+        status: matches.is_present("status"),
+        progress: matches.is_present("progress"),
+        options: Options {
+            insensitive: matches.is_present("insensitive"),
+            key,
+            separator: matches.value_of("separator").map(String::from),
+            ignore_blank: matches.is_present("ignore_blank"),
+            ignore_trailing: matches.is_present("ignore_trailing"),
+        },
     })
 }
 
-enum Column<'a> {
-    Column1(&'a str),
-    Column2(&'a str),
-    Column3(&'a str),
-}
+// Returns the exit status, which is only ever not 0 with --status: then 1
+// when the files differ, and 2 when something went wrong, as it is for any
+// error
+pub fn run(config: Config) -> MyResult<i32> {
+    if config.status {
+        // Only the last column has lines common to every file
+        return Ok(match comm(&config, &mut io::sink()) {
+            Ok(counts) if counts[..counts.len() - 1].iter().all(|&n| n == 0) => 0,
+            Ok(_) => 1,
+            Err(e) => {
+                eprintln!("{}", e);
+                2
+            }
+        });
+    }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let file1 = &config.file1;
-    let file2 = &config.file2;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    comm(&config, &mut out)?;
+    out.flush()?;
 
-    if file1 == "-" && file2 == "-" {
+    Ok(0)
+}
+
+// Returns how many lines each column has
+fn comm(config: &Config, out: &mut impl Write) -> MyResult<Vec<usize>> {
+    if config.files.iter().filter(|file| *file == "-").count() > 1 {
         return Err(From::from("Both input files cannot be STDIN (\"-\")"));
     }
 
-    let case_fix = |line: String| {
-        if config.insensitive {
-            line.to_lowercase()
+    let tags: &[&str] = if config.files.len() == 3 {
+        &TAGS3
+    } else {
+        &TAGS2
+    };
+
+    // A column is preceded by a delimiter for each visible column before it
+    let mut counts = vec![0; config.show.len()];
+    let mut printer = |col: usize, line: &str| -> io::Result<()> {
+        counts[col] += 1;
+        if config.count || config.status {
+            return Ok(());
+        }
+
+        let leading = if config.flat {
+            0
         } else {
-            line
+            config.show[..col].iter().filter(|&&show| show).count()
+        };
+
+        if config.show[col] {
+            match config.format {
+                Format::Columns => writeln!(out, "{}{}", config.delimiter.repeat(leading), line)?,
+                Format::Tsv => writeln!(out, "{}\t{}", tags[col], line)?,
+                Format::Json => writeln!(
+                    out,
+                    "{{\"column\":\"{}\",\"line\":{}}}",
+                    tags[col],
+                    json_string(line)
+                )?,
+            }
         }
+        Ok(())
     };
 
-    let printer = |col: Column| {
-        let mut output = vec![];
+    // The lines and bytes read from each file, with bytes counting the
+    // newlines lines() drops
+    let read: Vec<(Cell<usize>, Cell<u64>)> = config
+        .files
+        .iter()
+        .map(|_| (Cell::new(0), Cell::new(0)))
+        .collect();
+    let mut inputs = vec![];
+    for (file, (lines, bytes)) in config.files.iter().zip(&read) {
+        inputs.push(open(file)?.lines().inspect(move |line| {
+            if let Ok(line) = line {
+                lines.set(lines.get() + 1);
+                bytes.set(bytes.get() + line.len() as u64 + 1);
+            }
+        }));
+    }
+
+    // Only regular files have a size worth reporting against
+    let sizes: Vec<Option<u64>> = config
+        .files
+        .iter()
+        .map(|file| match file.as_str() {
+            "-" => None,
+            _ => fs::metadata(file)
+                .ok()
+                .filter(|meta| meta.is_file())
+                .map(|meta| meta.len()),
+        })
+        .collect();
+    let report = || {
+        let read: Vec<_> = read
+            .iter()
+            .map(|(lines, bytes)| (lines.get(), bytes.get()))
+            .collect();
+        eprintln!("{}", progress(&config.files, &read, &sizes));
+    };
+
+    let files = config.files.len();
+    let mut last = Instant::now();
+    for merged in merge(inputs, config.options.clone()) {
+        let (mask, line) = merged?;
+        printer(column(mask, files), &line)?;
+
+        if config.progress && last.elapsed() >= Duration::from_secs(1) {
+            report();
+            last = Instant::now();
+        }
+    }
+    if config.progress {
+        report();
+    }
 
-        match col {
-            Column1(l) => {
-                if config.show_col1 {
-                    output.push(l);
+    if config.count {
+        let visible = (0..counts.len()).filter(|&col| config.show[col]);
+        match config.format {
+            Format::Columns => {
+                let counts: Vec<_> = visible.map(|col| counts[col].to_string()).collect();
+                writeln!(out, "{}", counts.join(&config.delimiter))?;
+            }
+            Format::Tsv => {
+                for col in visible {
+                    writeln!(out, "{}\t{}", tags[col], counts[col])?;
+                }
+            }
+            Format::Json => {
+                for col in visible {
+                    writeln!(
+                        out,
+                        "{{\"column\":\"{}\",\"count\":{}}}",
+                        tags[col], counts[col]
+                    )?;
                 }
             }
-            Column2(l) => {
-                if config.show_col2 {
-                    if config.show_col1 {
-                        output.push("");
-                    }
+        }
+    }
 
-                    output.push(l);
+    Ok(counts)
+}
+
+/// Classifies the lines of two sorted inputs the way the command does, so
+/// other crates don't need to run it and parse its output. A line in both
+/// inputs comes out once, the way the first input has it.
+///
+/// ```
+/// use commr::{classify, Column, Options};
+///
+/// let lines1 = ["a", "b"].map(String::from);
+/// let lines2 = ["b", "c"].map(String::from);
+/// let classified: Vec<_> = classify(lines1, lines2, Options::default()).collect();
+/// assert_eq!(
+///     classified,
+///     [
+///         (Column::Column1, "a".to_string()),
+///         (Column::Column3, "b".to_string()),
+///         (Column::Column2, "c".to_string()),
+///     ]
+/// );
+/// ```
+pub fn classify<'a, I1, I2>(
+    lines1: I1,
+    lines2: I2,
+    options: Options,
+) -> impl Iterator<Item = (Column, String)> + 'a
+where
+    I1: IntoIterator<Item = String>,
+    I1::IntoIter: 'a,
+    I2: IntoIterator<Item = String>,
+    I2::IntoIter: 'a,
+{
+    let inputs: Vec<Box<dyn Iterator<Item = io::Result<String>> + 'a>> = vec![
+        Box::new(lines1.into_iter().map(Ok)),
+        Box::new(lines2.into_iter().map(Ok)),
+    ];
+    // Nothing here can fail to read
+    merge(inputs, options)
+        .map_while(Result::ok)
+        .map(|(mask, line)| {
+            let column = match mask {
+                1 => Column::Column1,
+                2 => Column::Column2,
+                _ => Column::Column3,
+            };
+            (column, line)
+        })
+}
+
+// Merges sorted inputs, yielding each line along with the mask of the
+// files having it: the smallest key goes first, with the line the way the
+// first of those files has it
+fn merge<I>(
+    mut inputs: Vec<I>,
+    options: Options,
+) -> impl Iterator<Item = io::Result<(usize, String)>>
+where
+    I: Iterator<Item = io::Result<String>>,
+{
+    let mut lines: Vec<Option<(String, String)>> = vec![];
+    let mut started = false;
+    iter::from_fn(move || {
+        if !started {
+            started = true;
+            for input in inputs.iter_mut() {
+                match next_line(input, &options) {
+                    Ok(line) => lines.push(line),
+                    Err(e) => return Some(Err(e)),
                 }
             }
-            Column3(l) => {
-                if config.show_col3 {
-                    if config.show_col1 {
-                        output.push("");
-                    }
+        }
 
-                    if config.show_col2 {
-                        output.push("");
-                    }
+        let first = (0..lines.len())
+            .filter(|&i| lines[i].is_some())
+            .min_by_key(|&i| lines[i].as_ref().map(|(key, _)| key))?;
+        let key = &lines[first].as_ref().unwrap().0;
+        let mask = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| other.as_ref().is_some_and(|(other, _)| other == key))
+            .fold(0, |mask, (i, _)| mask | 1 << i);
+        let (_, line) = lines[first].take().unwrap();
 
-                    output.push(l);
+        for (i, input) in inputs.iter_mut().enumerate() {
+            if mask & 1 << i != 0 {
+                match next_line(input, &options) {
+                    Ok(next) => lines[i] = next,
+                    Err(e) => return Some(Err(e)),
                 }
             }
         }
+        Some(Ok((mask, line)))
+    })
+}
+
+// The next line worth comparing, along with its key: lines are compared on
+// a key, but printed as they are
+fn next_line(
+    input: &mut impl Iterator<Item = io::Result<String>>,
+    options: &Options,
+) -> io::Result<Option<(String, String)>> {
+    for line in input {
+        let line = line?;
+        if options.ignore_blank && line.trim().is_empty() {
+            continue;
+        }
 
-        if !output.is_empty() {
-            println!("{}", output.join(&config.delimiter));
+        let mut key = match options.key {
+            Some(fields) => key_fields(&line, fields, options.separator.as_deref()),
+            None => line.clone(),
+        };
+        if options.ignore_trailing {
+            key.truncate(key.trim_end().len());
+        }
+        if options.insensitive {
+            key = key.to_lowercase();
         }
+        return Ok(Some((key, line)));
+    }
+    Ok(None)
+}
+
+// The column, counting from 0, of the lines in the files of the mask: first
+// those unique to each file, then those in just two of them, then in all
+fn column(mask: usize, files: usize) -> usize {
+    match files {
+        3 => [0, 0, 1, 3, 2, 4, 5, 6][mask],
+        _ => mask - 1,
+    }
+}
+
+// The fields from first to last, counting from 1, split by the separator or
+// by runs of whitespace
+fn key_fields(line: &str, (first, last): (usize, usize), separator: Option<&str>) -> String {
+    let fields: Vec<&str> = match separator {
+        Some(separator) => line.split(separator).collect(),
+        None => line.split_whitespace().collect(),
     };
 
-    let mut lines1 = open(file1)?.lines().filter_map(Result::ok).map(case_fix);
-    let mut lines2 = open(file2)?.lines().filter_map(Result::ok).map(case_fix);
+    fields
+        .get(first - 1..last.min(fields.len()))
+        .unwrap_or_default()
+        .join(separator.unwrap_or(" "))
+}
 
-    let mut line1 = lines1.next();
-    let mut line2 = lines2.next();
+// Either N or N,M, with fields counting from 1
+fn parse_key(key: &str) -> MyResult<(usize, usize)> {
+    let field = |n: &str| match n.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("Invalid key \"{}\"", key)),
+    };
 
-    while line1.is_some() || line2.is_some() {
-        match (&line1, &line2) {
-            (Some(l1), Some(l2)) => match l1.cmp(l2) {
-                Equal => {
-                    printer(Column3(l1));
-                    line1 = lines1.next();
-                    line2 = lines2.next();
-                }
-                Less => {
-                    printer(Column1(l1));
-                    line1 = lines1.next();
-                }
-                Greater => {
-                    printer(Column2(l2));
-                    line2 = lines2.next();
-                }
-            },
-            (Some(l1), None) => {
-                printer(Column1(l1));
-                line1 = lines1.next();
-            }
-            (None, Some(l2)) => {
-                printer(Column2(l2));
-                line2 = lines2.next();
-            }
-            _ => (),
+    let (first, last) = match key.split_once(',') {
+        Some((first, last)) => (field(first)?, field(last)?),
+        None => (field(key)?, field(key)?),
+    };
+    if first > last {
+        return Err(format!("Invalid key \"{}\"", key).into());
+    }
+
+    Ok((first, last))
+}
+
+// Quoted and escaped as a JSON string
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
         }
     }
+    quoted.push('"');
+
+    quoted
+}
 
-    Ok(())
+// How much of each file has been read, with bytes only when the size is
+// known: "file1.txt: 10 lines, 200 of 1000 bytes; -: 5 lines"
+fn progress(files: &[String], read: &[(usize, u64)], sizes: &[Option<u64>]) -> String {
+    files
+        .iter()
+        .zip(read)
+        .zip(sizes)
+        .map(|((file, (lines, bytes)), size)| match size {
+            Some(size) => format!("{}: {} lines, {} of {} bytes", file, lines, bytes, size),
+            None => format!("{}: {} lines", file, lines),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
@@ -193,3 +636,130 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
         ))),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        classify, column, comm, json_string, key_fields, parse_key, progress, Column, Config,
+        Format, Options,
+    };
+    use std::fs;
+
+    #[test]
+    fn test_comm() {
+        let config = Config {
+            files: vec![
+                "tests/inputs/file1.txt".to_string(),
+                "tests/inputs/file2.txt".to_string(),
+            ],
+            show: vec![true; 3],
+            delimiter: "\t".to_string(),
+            flat: false,
+            format: Format::Columns,
+            count: false,
+            status: false,
+            progress: false,
+            options: Options::default(),
+        };
+        let expected = fs::read_to_string("tests/expected/file1_file2.out").unwrap();
+
+        let mut out = vec![];
+        assert!(comm(&config, &mut out).is_ok());
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_classify() {
+        let lines = |lines: &[&str]| {
+            lines
+                .iter()
+                .map(|line| line.to_string())
+                .collect::<Vec<_>>()
+        };
+        let classified: Vec<_> = classify(
+            lines(&["B", "a", "c"]),
+            lines(&["b", "d"]),
+            Options::default(),
+        )
+        .collect();
+        assert_eq!(
+            classified,
+            [
+                (Column::Column1, "B".to_string()),
+                (Column::Column1, "a".to_string()),
+                (Column::Column2, "b".to_string()),
+                (Column::Column1, "c".to_string()),
+                (Column::Column2, "d".to_string()),
+            ]
+        );
+
+        let options = Options {
+            insensitive: true,
+            ..Default::default()
+        };
+        let classified: Vec<_> =
+            classify(lines(&["a", "B", "c"]), lines(&["b", "d"]), options).collect();
+        assert_eq!(
+            classified,
+            [
+                (Column::Column1, "a".to_string()),
+                (Column::Column3, "B".to_string()),
+                (Column::Column1, "c".to_string()),
+                (Column::Column2, "d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_column() {
+        assert_eq!(column(0b01, 2), 0);
+        assert_eq!(column(0b10, 2), 1);
+        assert_eq!(column(0b11, 2), 2);
+
+        assert_eq!(column(0b001, 3), 0);
+        assert_eq!(column(0b010, 3), 1);
+        assert_eq!(column(0b100, 3), 2);
+        assert_eq!(column(0b011, 3), 3);
+        assert_eq!(column(0b101, 3), 4);
+        assert_eq!(column(0b110, 3), 5);
+        assert_eq!(column(0b111, 3), 6);
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("2").unwrap(), (2, 2));
+        assert_eq!(parse_key("1,3").unwrap(), (1, 3));
+
+        for bad in ["0", "foo", "3,1", "1,", "-1"] {
+            let res = parse_key(bad);
+            assert_eq!(
+                res.unwrap_err().to_string(),
+                format!("Invalid key \"{}\"", bad)
+            );
+        }
+    }
+
+    #[test]
+    fn test_key_fields() {
+        assert_eq!(key_fields("a  b\tc d", (2, 3), None), "b c");
+        assert_eq!(key_fields("a:b::d", (3, 4), Some(":")), ":d");
+        assert_eq!(key_fields("a b", (2, 5), None), "b");
+        assert_eq!(key_fields("a b", (3, 3), None), "");
+    }
+
+    #[test]
+    fn test_progress() {
+        let files = vec!["file1.txt".to_string(), "-".to_string()];
+        assert_eq!(
+            progress(&files, &[(10, 200), (5, 50)], &[Some(1000), None]),
+            "file1.txt: 10 lines, 200 of 1000 bytes; -: 5 lines"
+        );
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("foo"), "\"foo\"");
+        assert_eq!(json_string("a \"b\"\\c"), "\"a \\\"b\\\"\\\\c\"");
+        assert_eq!(json_string("\tx\r\u{1}"), "\"\\tx\\r\\u0001\"");
+    }
+}
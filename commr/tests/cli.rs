@@ -8,6 +8,8 @@ const EMPTY: &str = "tests/inputs/empty.txt";
 const FILE1: &str = "tests/inputs/file1.txt";
 const FILE2: &str = "tests/inputs/file2.txt";
 const BLANK: &str = "tests/inputs/blank.txt";
+const UPPER: &str = "tests/inputs/upper.txt";
+const FILE3: &str = "tests/inputs/file3.txt";
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -42,7 +44,7 @@ fn dies_bad_file1() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&[&bad, FILE1])
+        .args([&bad, FILE1])
         .assert()
         .failure()
         .stderr(predicate::str::is_match(expected)?);
@@ -55,7 +57,7 @@ fn dies_bad_file2() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&[FILE1, &bad])
+        .args([FILE1, &bad])
         .assert()
         .failure()
         .stderr(predicate::str::is_match(expected)?);
@@ -67,7 +69,7 @@ fn dies_bad_file2() -> TestResult {
 fn dies_both_stdin() -> TestResult {
     let expected = "Both input files cannot be STDIN (\"-\")";
     Command::cargo_bin(PRG)?
-        .args(&["-", "-"])
+        .args(["-", "-"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -86,11 +88,7 @@ fn run(args: &[&str], expected_file: &str) -> TestResult {
 }
 
 // --------------------------------------------------
-fn run_stdin(
-    args: &[&str],
-    input_file: &str,
-    expected_file: &str,
-) -> TestResult {
+fn run_stdin(args: &[&str], input_file: &str, expected_file: &str) -> TestResult {
     let input = fs::read_to_string(input_file)?;
     let expected = fs::read_to_string(expected_file)?;
     Command::cargo_bin(PRG)?
@@ -242,6 +240,12 @@ fn file1_file2_123_i() -> TestResult {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn file1_upper_i() -> TestResult {
+    run(&["-i", FILE1, UPPER], "tests/expected/file1_upper.i.out")
+}
+
 // --------------------------------------------------
 #[test]
 fn stdin_file1() -> TestResult {
@@ -334,12 +338,317 @@ fn file1_file2_123_delim() -> TestResult {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn file1_file2_intersect() -> TestResult {
+    run(
+        &["--intersect", FILE1, FILE2],
+        "tests/expected/file1_file2.intersect.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_union() -> TestResult {
+    run(
+        &["--union", FILE1, FILE2],
+        "tests/expected/file1_file2.union.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_diff() -> TestResult {
+    run(
+        &["--diff", FILE1, FILE2],
+        "tests/expected/file1_file2.diff.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_set_and_suppress() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--union", "-1", FILE1, FILE2])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 fn blank_file1() -> TestResult {
     run(&[BLANK, FILE1], "tests/expected/blank_file1.out")
 }
 
+// --------------------------------------------------
+#[test]
+fn file1_blank() -> TestResult {
+    run(&[FILE1, BLANK], "tests/expected/file1_blank.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_blank_1() -> TestResult {
+    run(&["-1", FILE1, BLANK], "tests/expected/file1_blank.1.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_blank_2() -> TestResult {
+    run(&["-2", FILE1, BLANK], "tests/expected/file1_blank.2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_blank_3() -> TestResult {
+    run(&["-3", FILE1, BLANK], "tests/expected/file1_blank.3.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_tsv() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "tsv", "-3", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout("file2\tB\nfile1\ta\nfile1\tb\nfile1\td\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_json() -> TestResult {
+    let expected = concat!(
+        "{\"column\":\"file2\",\"line\":\"B\"}\n",
+        "{\"column\":\"file1\",\"line\":\"a\"}\n",
+        "{\"column\":\"file1\",\"line\":\"b\"}\n",
+        "{\"column\":\"both\",\"line\":\"c\"}\n",
+        "{\"column\":\"file1\",\"line\":\"d\"}\n",
+    );
+    Command::cargo_bin(PRG)?
+        .args(["--format", "json", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ids1_ids2_key() -> TestResult {
+    run(
+        &["-k", "1", "tests/inputs/ids1.txt", "tests/inputs/ids2.txt"],
+        "tests/expected/ids1_ids2.k1.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn key_separator() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-i", "-t", " ", "-k", "2", "-", "tests/inputs/ids2.txt"])
+        .write_stdin("1 apple\n2 banana\n3 cherry\n")
+        .assert()
+        .success()
+        .stdout("\t\t1 apple\n2 banana\n\t3 cherries\n3 cherry\n\t4 date\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_key() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-k", "2,1", FILE1, FILE2])
+        .assert()
+        .failure()
+        .stderr("Invalid key \"2,1\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_file3() -> TestResult {
+    run(
+        &[FILE1, FILE2, FILE3],
+        "tests/expected/file1_file2_file3.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_file3_suppressed() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-1234", "-6", FILE1, FILE2, FILE3])
+        .assert()
+        .success()
+        .stdout("a\nb\n\tc\nd\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--intersect", FILE1, FILE2, FILE3])
+        .assert()
+        .success()
+        .stdout("c\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_column4_two_files() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-4", FILE1, FILE2])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("FILE3"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn file1_file2_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout("3\t1\t1\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--count", "-2", "-d", ":", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout("3:1\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--count", "--format", "tsv", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout("file1\t3\nfile2\t1\nboth\t1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn status() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--status", FILE1, FILE1])
+        .assert()
+        .code(0)
+        .stdout("");
+
+    Command::cargo_bin(PRG)?
+        .args(["--status", FILE1, FILE2])
+        .assert()
+        .code(1)
+        .stdout("");
+
+    // Only the case differs
+    Command::cargo_bin(PRG)?
+        .args(["--status", "-i", FILE1, "-"])
+        .write_stdin("A\nB\nC\nD\n")
+        .assert()
+        .code(0);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_status_bad_file() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["--status", FILE1, &bad])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("os error 2"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_status_bad_args() -> TestResult {
+    // Usage errors are errors, not files that differ
+    Command::cargo_bin(PRG)?
+        .args(["--status", "-k", "0", FILE1, FILE2])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid key"));
+
+    Command::cargo_bin(PRG)?
+        .args(["--status", FILE1])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("FILE2"));
+
+    // Just like without --status
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args([FILE1, &bad])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("os error 2"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ignore_blank_lines() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-B", BLANK, FILE1])
+        .assert()
+        .success()
+        .stdout("\ta\n\tb\n\tc\n\td\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--ignore-blank-lines", "--status", FILE1, "-"])
+        .write_stdin("\na\n  \nb\nc\n\t\nd\n\n")
+        .assert()
+        .code(0);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ignore_trailing_space() -> TestResult {
+    // Lines are still printed as they are
+    Command::cargo_bin(PRG)?
+        .args(["-Z", FILE1, "-"])
+        .write_stdin("a\r\nb \t\ne  \n")
+        .assert()
+        .success()
+        .stdout("\t\ta\n\t\tb\nc\nd\n\te  \n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--ignore-trailing-space", "--status", FILE1, "-"])
+        .write_stdin("a\r\nb\r\nc\r\nd \r\n")
+        .assert()
+        .code(0);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn progress() -> TestResult {
+    let expected = fs::read_to_string("tests/expected/file1_file2.out")?;
+    Command::cargo_bin(PRG)?
+        .args(["--progress", FILE1, FILE2])
+        .assert()
+        .success()
+        .stdout(expected)
+        .stderr(format!(
+            "{}: 4 lines, 8 of 8 bytes; {}: 2 lines, 4 of 4 bytes\n",
+            FILE1, FILE2
+        ));
+
+    // STDIN has no size to report against
+    Command::cargo_bin(PRG)?
+        .args(["--progress", FILE1, "-"])
+        .write_stdin("b\nc\n")
+        .assert()
+        .success()
+        .stderr(format!("{}: 4 lines, 8 of 8 bytes; -: 2 lines\n", FILE1));
+    Ok(())
+}
+
 //// --------------------------------------------------
 //#[test]
 //fn file1_blanks() -> TestResult {
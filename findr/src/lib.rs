@@ -1,7 +1,16 @@
 use crate::EntryType::*;
 use clap::{App, Arg};
-use regex::Regex;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+use std::env;
 use std::error::Error;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::time::{Duration, SystemTime};
 use walkdir::{DirEntry, WalkDir};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
@@ -13,18 +22,92 @@ enum EntryType {
     Link,
 }
 
+// A test on an entry
+#[derive(Debug)]
+enum Predicate {
+    Name(Regex),
+    Path(Regex),
+    Type(EntryType),
+    // How the size must compare to a number of units of some bytes
+    Size(Ordering, u64, u64),
+    // Likewise for how long ago the entry was modified, in seconds
+    Age(Ordering, u64, u64),
+    Newer(SystemTime),
+    Empty,
+}
+
+// Tests combined as find does, with an empty And always true
+#[derive(Debug)]
+enum Expr {
+    Test(Predicate),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+// Which symlinks are followed: none, only the paths given, or all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Follow {
+    Never,
+    Paths,
+    Always,
+}
+
 #[derive(Debug)]
 pub struct Config {
     paths: Vec<String>,
-    names: Vec<Regex>,
-    entry_types: Vec<EntryType>,
+    expr: Expr,
+    follow: Follow,
+    // Directories not descended into, nor listed
+    prune: Expr,
+    hidden: bool,
+    // Whether .git is skipped and .gitignore files honored
+    ignore: bool,
 }
 
+// With any of these, the arguments from the first test on are an expression
+const OPERATORS: [&str; 8] = ["(", ")", "!", "-a", "-o", "--not", "--and", "--or"];
+
+// The tests, by their long options, all but --empty taking a value
+const TESTS: [&str; 10] = [
+    "name",
+    "iname",
+    "path",
+    "regex-path",
+    "type",
+    "size",
+    "mtime",
+    "mmin",
+    "newer",
+    "empty",
+];
+
 pub fn get_args() -> MyResult<Config> {
+    let args: Vec<String> = env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let start = if args.iter().any(|arg| OPERATORS.contains(&arg.as_str())) {
+        args.iter()
+            .skip(1)
+            .position(|arg| starts_expression(arg))
+            .map_or(args.len(), |i| i + 1)
+    } else {
+        args.len()
+    };
+
     let matches = App::new("findr")
         .version("0.1.0")
         .author("Felipe Balbi <felipe@balbi.sh>")
         .about("Rust find")
+        .after_help(
+            "Tests can be combined with -a, -o, ! and parentheses, as in\n\
+             findr . -t f ( -n '[.]rs$' -o -n '[.]toml$' ) ! --path '*/target/*'\n\
+             Each test then takes a single value, and options must come first.\n\
+             Tests next to each other in an expression must all match, as in find,\n\
+             while outside of one a repeated -n, -t or --path matches any of its\n\
+             values. So as not to mix the two up, an expression needs -a or -o\n\
+             between repeats of -n or --iname, -t, and --path or --regex-path.",
+        )
         .arg(
             Arg::with_name("paths")
                 .value_name("PATH")
@@ -33,6 +116,24 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(true)
                 .multiple(true),
         )
+        .arg(
+            Arg::with_name("never_follow")
+                .short("P")
+                .help("Never follow symlinks, the default")
+                .overrides_with_all(&["follow_paths", "follow"]),
+        )
+        .arg(
+            Arg::with_name("follow_paths")
+                .short("H")
+                .help("Follow symlinks among the paths, but not below them")
+                .overrides_with_all(&["never_follow", "follow"]),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("L")
+                .help("Follow all symlinks, leaving only broken ones of type l")
+                .overrides_with_all(&["never_follow", "follow_paths"]),
+        )
         .arg(
             Arg::with_name("names")
                 .short("n")
@@ -42,6 +143,53 @@ pub fn get_args() -> MyResult<Config> {
                 .takes_value(true)
                 .multiple(true),
         )
+        .arg(
+            Arg::with_name("inames")
+                .long("iname")
+                .value_name("NAME")
+                .help("Name or Regex to match, ignoring case")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("glob")
+                .long("glob")
+                .help("Match names against shell globs instead of Regexes"),
+        )
+        .arg(
+            Arg::with_name("prune")
+                .long("prune")
+                .value_name("NAME")
+                .help("Name or Regex of directories to skip, except the paths")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("hidden")
+                .long("hidden")
+                .help("Include hidden files and directories"),
+        )
+        .arg(
+            Arg::with_name("no_ignore")
+                .long("no-ignore")
+                .help("Include .git and what .gitignore files leave out"),
+        )
+        .arg(
+            Arg::with_name("path_globs")
+                .long("path")
+                .value_name("GLOB")
+                .help("Glob to match against the whole path")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("path_regexes")
+                .long("regex-path")
+                .value_name("REGEX")
+                .help("Regex to match against the whole path")
+                .takes_value(true)
+                .multiple(true),
+        )
         .arg(
             Arg::with_name("types")
                 .short("t")
@@ -52,76 +200,177 @@ pub fn get_args() -> MyResult<Config> {
                 .possible_values(&["f", "l", "d"])
                 .multiple(true),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .value_name("[+|-]N[c|k|M|G]")
+                .help("Size to filter, in 512-byte blocks unless given a unit")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("mtime")
+                .long("mtime")
+                .value_name("[+|-]DAYS")
+                .help("Days since last modified, ignoring any fraction")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("mmin")
+                .long("mmin")
+                .value_name("[+|-]MINUTES")
+                .help("Minutes since last modified, ignoring any fraction")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::with_name("newer")
+                .long("newer")
+                .value_name("FILE")
+                .help("Modified more recently than FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("empty")
+                .long("empty")
+                .help("Empty files and directories"),
+        )
+        .get_matches_from(&args[..start]);
 
     let paths = matches.values_of_lossy("paths").unwrap();
+    let glob = matches.is_present("glob");
+    let follow = if matches.is_present("follow") {
+        Follow::Always
+    } else if matches.is_present("follow_paths") {
+        Follow::Paths
+    } else {
+        Follow::Never
+    };
+    // Any value of an option doing
+    let any = |options: &[(&str, &str)]| -> MyResult<Option<Expr>> {
+        let mut any = vec![];
+        for (arg, option) in options {
+            for value in matches.values_of_lossy(arg).unwrap_or_default() {
+                any.push(Expr::Test(predicate(option, &value, glob)?));
+            }
+        }
+        Ok((!any.is_empty()).then_some(Expr::Or(any)))
+    };
+    let prune = Expr::Or(any(&[("prune", "prune")])?.into_iter().collect());
+    let hidden = matches.is_present("hidden");
+    let ignore = !matches.is_present("no_ignore");
 
-    let names = matches
-        .values_of_lossy("names")
-        .map(|vals| {
-            vals.iter()
-                .map(|name| Regex::new(&name).map_err(|_| format!("Invalid --name \"{}\"", name)))
-                .collect()
-        })
-        .transpose()?
-        .unwrap_or_default();
-
-    let entry_types = matches
-        .values_of_lossy("types")
-        .map(|vals| {
-            vals.iter()
-                .map(|t| match t.as_str() {
-                    "f" => File,
-                    "l" => Link,
-                    "d" => Dir,
-                    _ => unreachable!("Invalid type"),
-                })
-                .collect()
-        })
-        .unwrap_or_default();
+    if start < args.len() {
+        return Ok(Config {
+            paths,
+            expr: parse_expr(&args[start..], glob)?,
+            follow,
+            prune,
+            hidden,
+            ignore,
+        });
+    }
+
+    // Otherwise all options must match
+    let mut all = vec![];
+    all.extend(any(&[("names", "name"), ("inames", "iname")])?);
+    all.extend(any(&[
+        ("path_globs", "path"),
+        ("path_regexes", "regex-path"),
+    ])?);
+    all.extend(any(&[("types", "type")])?);
+    for option in ["size", "mtime", "mmin", "newer"] {
+        if let Some(value) = matches.value_of(option) {
+            all.push(Expr::Test(predicate(option, value, glob)?));
+        }
+    }
+    if matches.is_present("empty") {
+        all.push(Expr::Test(Predicate::Empty));
+    }
 
     Ok(Config {
         paths,
-        names,
-        entry_types,
+        expr: Expr::And(all),
+        follow,
+        prune,
+        hidden,
+        ignore,
     })
 }
 
 pub fn run(config: Config) -> MyResult<()> {
-    let type_filter = |entry: &DirEntry| {
-        config.entry_types.is_empty()
-            || config
-                .entry_types
-                .iter()
-                .any(|entry_type| match entry_type {
-                    File => is_file(&entry),
-                    Link => is_symlink(&entry),
-                    Dir => is_directory(&entry),
-                })
-    };
+    let now = SystemTime::now();
 
-    let name_filter = |entry: &DirEntry| {
-        config.names.is_empty()
-            || config
-                .names
-                .iter()
-                .any(|re| re.is_match(&entry.file_name().to_string_lossy()))
-    };
+    for path in &config.paths {
+        // Loops through followed links are reported as errors
+        let mut walk = WalkDir::new(path)
+            .follow_links(config.follow == Follow::Always)
+            .follow_root_links(config.follow != Follow::Never)
+            .into_iter();
+        let mut entries = vec![];
+        // Ignore files match against full paths, as those from above the
+        // path have nothing else in common with its entries
+        let full = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        // The ignore files found so far, by the depth of their directory,
+        // with None for those above the path, which always apply
+        let mut ignores: Vec<(Option<usize>, Gitignore)> = if config.ignore {
+            parent_ignores(&full)
+                .into_iter()
+                .map(|gitignore| (None, gitignore))
+                .collect()
+        } else {
+            vec![]
+        };
 
-    for path in config.paths {
-        let entries = WalkDir::new(path)
-            .into_iter()
-            .filter_map(|e| match e {
-                Err(e) => {
-                    eprintln!("{}", e);
-                    None
+        while let Some(e) = walk.next() {
+            let (entry, depth) = match e {
+                Ok(entry) => {
+                    let depth = entry.depth();
+                    (entry, depth)
+                }
+                // A broken link can't be followed, so it is taken as is
+                Err(e) => match broken_link(&e) {
+                    Some(entry) => (entry, e.depth()),
+                    None => {
+                        eprintln!("{}", e);
+                        continue;
+                    }
+                },
+            };
+            // The walk goes through a path that is a link with -H, but
+            // still reports it as a link
+            let entry = if config.follow == Follow::Paths && depth == 0 && is_symlink(&entry) {
+                followed(path).unwrap_or(entry)
+            } else {
+                entry
+            };
+            let full_path = match entry.path().strip_prefix(path) {
+                Ok(rest) if depth > 0 => full.join(rest),
+                _ => full.clone(),
+            };
+            // Leaving behind those of the directories done with
+            ignores.retain(|(dir_depth, _)| *dir_depth < Some(depth));
+            if depth > 0 && skipped(&config, &entry, &full_path, &ignores) {
+                if is_directory(&entry) {
+                    walk.skip_current_dir();
+                }
+                continue;
+            }
+            // Skipping a directory before it is read at all
+            if depth > 0 && is_directory(&entry) && eval(&config.prune, &entry, now) {
+                walk.skip_current_dir();
+                continue;
+            }
+            if config.ignore && is_directory(&entry) {
+                if let Some(gitignore) = ignore_file(&full_path, &full_path.join(".gitignore")) {
+                    ignores.push((Some(depth), gitignore));
                 }
-                Ok(entry) => Some(entry),
-            })
-            .filter(type_filter)
-            .filter(name_filter)
-            .map(|entry| entry.path().display().to_string())
-            .collect::<Vec<_>>();
+            }
+            if eval(&config.expr, &entry, now) {
+                entries.push(entry.path().display().to_string());
+            }
+        }
 
         println!("{}", entries.join("\n"));
     }
@@ -129,14 +378,406 @@ pub fn run(config: Config) -> MyResult<()> {
     Ok(())
 }
 
+// Whether an entry is hidden, or .git or ignored, and not asked for
+fn skipped(
+    config: &Config,
+    entry: &DirEntry,
+    full_path: &Path,
+    ignores: &[(Option<usize>, Gitignore)],
+) -> bool {
+    let name = entry.file_name().to_string_lossy();
+    if !config.hidden && name.starts_with('.') {
+        return true;
+    }
+    // The innermost .gitignore with a say decides
+    config.ignore
+        && (name == ".git"
+            || ignores
+                .iter()
+                .rev()
+                .map(|(_, gitignore)| gitignore.matched(full_path, is_directory(entry)))
+                .find(|matched| !matched.is_none())
+                .is_some_and(|matched| matched.is_ignore()))
+}
+
+// The ignore files of the repository a path is in, if any, that the walk
+// won't come across: .git/info/exclude and the .gitignore files above it
+fn parent_ignores(path: &Path) -> Vec<Gitignore> {
+    let Some(top) = path.ancestors().position(|dir| dir.join(".git").exists()) else {
+        return vec![];
+    };
+    let mut dirs: Vec<&Path> = path.ancestors().skip(1).take(top).collect();
+    dirs.reverse();
+
+    let top = path.ancestors().nth(top).unwrap();
+    ignore_file(top, &top.join(".git/info/exclude"))
+        .into_iter()
+        .chain(
+            dirs.into_iter()
+                .filter_map(|dir| ignore_file(dir, &dir.join(".gitignore"))),
+        )
+        .collect()
+}
+
+// The rules of an ignore file, which apply from dir down
+fn ignore_file(dir: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+
+    let mut builder = GitignoreBuilder::new(dir);
+    if let Some(e) = builder.add(file) {
+        eprintln!("{}", e);
+    }
+    builder.build().map_err(|e| eprintln!("{}", e)).ok()
+}
+
+fn eval(expr: &Expr, entry: &DirEntry, now: SystemTime) -> bool {
+    match expr {
+        Expr::Test(predicate) => test(predicate, entry, now),
+        Expr::Not(expr) => !eval(expr, entry, now),
+        Expr::And(all) => all.iter().all(|expr| eval(expr, entry, now)),
+        Expr::Or(any) => any.iter().any(|expr| eval(expr, entry, now)),
+    }
+}
+
+fn test(predicate: &Predicate, entry: &DirEntry, now: SystemTime) -> bool {
+    match predicate {
+        Predicate::Name(re) => re.is_match(&entry.file_name().to_string_lossy()),
+        Predicate::Path(re) => re.is_match(&entry.path().to_string_lossy()),
+        Predicate::Type(File) => is_file(entry),
+        Predicate::Type(Link) => is_symlink(entry),
+        Predicate::Type(Dir) => is_directory(entry),
+        // Sizes are rounded up to whole units, as find does
+        Predicate::Size(ordering, n, unit) => entry
+            .metadata()
+            .is_ok_and(|meta| meta.len().div_ceil(*unit).cmp(n) == *ordering),
+        // Ages are whole units, with any fraction ignored as find does, and
+        // entries from the future are no age at all
+        Predicate::Age(ordering, n, unit) => modified(entry).is_some_and(|modified| {
+            let age = now
+                .duration_since(modified)
+                .unwrap_or(Duration::ZERO)
+                .as_secs();
+            (age / unit).cmp(n) == *ordering
+        }),
+        Predicate::Newer(newer) => modified(entry).is_some_and(|modified| modified > *newer),
+        // Only files and directories can be empty
+        Predicate::Empty => {
+            if is_directory(entry) {
+                fs::read_dir(entry.path()).is_ok_and(|mut dir| dir.next().is_none())
+            } else {
+                is_file(entry) && entry.metadata().is_ok_and(|meta| meta.len() == 0)
+            }
+        }
+    }
+}
+
+fn modified(entry: &DirEntry) -> Option<SystemTime> {
+    entry.metadata().ok().and_then(|meta| meta.modified().ok())
+}
+
 fn is_directory(entry: &DirEntry) -> bool {
     entry.file_type().is_dir()
 }
 
+// Followed links take the type of what they point to, as in find, so with
+// -L only broken ones are links
 fn is_symlink(entry: &DirEntry) -> bool {
     entry.file_type().is_symlink()
 }
 
+// The entry of a path, with any link followed
+fn followed(path: &str) -> Option<DirEntry> {
+    WalkDir::new(path)
+        .follow_links(true)
+        .max_depth(0)
+        .into_iter()
+        .next()?
+        .ok()
+}
+
+// The entry of a link the walk failed to follow for pointing nowhere
+fn broken_link(e: &walkdir::Error) -> Option<DirEntry> {
+    let path = e.path()?;
+    let missing = e
+        .io_error()
+        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound);
+    if !missing || !fs::symlink_metadata(path).is_ok_and(|meta| meta.is_symlink()) {
+        return None;
+    }
+
+    WalkDir::new(path)
+        .follow_root_links(false)
+        .into_iter()
+        .next()?
+        .ok()
+}
+
 fn is_file(entry: &DirEntry) -> bool {
     entry.file_type().is_file()
 }
+
+// The test an option asks for, by its long name
+fn predicate(option: &str, value: &str, glob: bool) -> MyResult<Predicate> {
+    let invalid = || format!("Invalid --{} \"{}\"", option, value);
+    let name = |insensitive| {
+        let pattern = if glob {
+            glob_to_regex(value)
+        } else {
+            value.to_string()
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(insensitive)
+            .build()
+            .map_err(|_| invalid())
+    };
+
+    Ok(match option {
+        "name" => Predicate::Name(name(false)?),
+        "iname" => Predicate::Name(name(true)?),
+        "prune" => Predicate::Name(name(false)?),
+        // With "*" in a glob also matching "/"
+        "path" => Predicate::Path(Regex::new(&glob_to_regex(value)).map_err(|_| invalid())?),
+        "regex-path" => Predicate::Path(Regex::new(value).map_err(|_| invalid())?),
+        "type" => Predicate::Type(match value {
+            "f" => File,
+            "l" => Link,
+            "d" => Dir,
+            _ => return Err(invalid().into()),
+        }),
+        "size" => {
+            let (ordering, n, unit) = parse_size(value)?;
+            Predicate::Size(ordering, n, unit)
+        }
+        "mtime" => {
+            let (ordering, n, unit) = parse_age(value, "--mtime", 24 * 60 * 60)?;
+            Predicate::Age(ordering, n, unit)
+        }
+        "mmin" => {
+            let (ordering, n, unit) = parse_age(value, "--mmin", 60)?;
+            Predicate::Age(ordering, n, unit)
+        }
+        "newer" => Predicate::Newer(
+            fs::metadata(value)
+                .and_then(|meta| meta.modified())
+                .map_err(|e| format!("{}: {}", value, e))?,
+        ),
+        _ => unreachable!("Invalid test"),
+    })
+}
+
+// Whether an argument is a test or an operator starting a test
+fn starts_expression(arg: &str) -> bool {
+    matches!(arg, "(" | "!" | "--not" | "-n" | "-t") || test_option(arg).is_some()
+}
+
+// The long name of the test an argument is, along with any "=VALUE" part
+fn test_option(arg: &str) -> Option<(&'static str, Option<&str>)> {
+    let (option, value) = match arg.split_once('=') {
+        Some((option, value)) => (option, Some(value)),
+        None => (arg, None),
+    };
+    let option = match option {
+        "-n" => "--name",
+        "-t" => "--type",
+        _ => option,
+    };
+    TESTS
+        .into_iter()
+        .find(|test| option.strip_prefix("--") == Some(test))
+        .map(|test| (test, value))
+}
+
+// The option a test repeats when given more than once, outside of an
+// expression matching any of the values rather than all of them
+fn repeatable(arg: &str) -> Option<&'static str> {
+    match test_option(arg)?.0 {
+        "name" | "iname" => Some("name"),
+        "path" | "regex-path" => Some("path"),
+        "type" => Some("type"),
+        _ => None,
+    }
+}
+
+type Tokens<'a> = Peekable<slice::Iter<'a, String>>;
+
+// expr: and [-o and]..., and: unary [[-a] unary]..., unary: ! unary,
+// ( expr ) or a test, just as find reads them
+fn parse_expr(args: &[String], glob: bool) -> MyResult<Expr> {
+    let mut tokens = args.iter().peekable();
+    let expr = parse_or(&mut tokens, glob)?;
+    match tokens.next() {
+        None => Ok(expr),
+        Some(token) => Err(format!("Unexpected \"{}\"", token).into()),
+    }
+}
+
+fn parse_or(tokens: &mut Tokens, glob: bool) -> MyResult<Expr> {
+    let mut any = vec![parse_and(tokens, glob)?];
+    while tokens
+        .next_if(|token| matches!(token.as_str(), "-o" | "--or"))
+        .is_some()
+    {
+        any.push(parse_and(tokens, glob)?);
+    }
+    Ok(if any.len() == 1 {
+        any.remove(0)
+    } else {
+        Expr::Or(any)
+    })
+}
+
+fn parse_and(tokens: &mut Tokens, glob: bool) -> MyResult<Expr> {
+    let mut last = tokens.peek().and_then(|token| repeatable(token));
+    let mut all = vec![parse_unary(tokens, glob)?];
+    while tokens
+        .peek()
+        .is_some_and(|token| !matches!(token.as_str(), ")" | "-o" | "--or"))
+    {
+        let joined = tokens
+            .next_if(|token| matches!(token.as_str(), "-a" | "--and"))
+            .is_some();
+        let next = tokens.peek().and_then(|token| repeatable(token));
+        if !joined && next.is_some() && next == last {
+            let token = tokens.peek().unwrap();
+            return Err(format!("Missing -a or -o before the repeated \"{}\"", token).into());
+        }
+        last = next;
+        all.push(parse_unary(tokens, glob)?);
+    }
+    Ok(if all.len() == 1 {
+        all.remove(0)
+    } else {
+        Expr::And(all)
+    })
+}
+
+fn parse_unary(tokens: &mut Tokens, glob: bool) -> MyResult<Expr> {
+    let token = tokens
+        .next()
+        .ok_or("Missing test at the end of the expression")?;
+    match token.as_str() {
+        "!" | "--not" => Ok(Expr::Not(Box::new(parse_unary(tokens, glob)?))),
+        "(" => {
+            let expr = parse_or(tokens, glob)?;
+            match tokens.next() {
+                Some(token) if token == ")" => Ok(expr),
+                _ => Err(From::from("Missing \")\" in the expression")),
+            }
+        }
+        _ => match test_option(token) {
+            Some(("empty", _)) => Ok(Expr::Test(Predicate::Empty)),
+            Some((option, value)) => {
+                let value = match value {
+                    Some(value) => value,
+                    None => tokens
+                        .next()
+                        .ok_or_else(|| format!("Missing value for \"{}\"", token))?,
+                };
+                Ok(Expr::Test(predicate(option, value, glob)?))
+            }
+            None => Err(format!("Unknown test \"{}\"", token).into()),
+        },
+    }
+}
+
+// "+N" is more than N units, "-N" less, and "N" exactly N
+fn parse_size(size: &str) -> MyResult<(Ordering, u64, u64)> {
+    let error = || format!("Invalid --size \"{}\"", size);
+
+    let (ordering, rest) = parse_ordering(size);
+    let (number, unit) = match rest.char_indices().last() {
+        Some((i, 'c')) => (&rest[..i], 1),
+        Some((i, 'k')) => (&rest[..i], 1024),
+        Some((i, 'M')) => (&rest[..i], 1024 * 1024),
+        Some((i, 'G')) => (&rest[..i], 1024 * 1024 * 1024),
+        _ => (rest, 512),
+    };
+    let number = number.parse().map_err(|_| error())?;
+
+    Ok((ordering, number, unit))
+}
+
+// Like sizes, but with the unit of seconds given by the option
+fn parse_age(age: &str, option: &str, unit: u64) -> MyResult<(Ordering, u64, u64)> {
+    let (ordering, number) = parse_ordering(age);
+    let number = number
+        .parse()
+        .map_err(|_| format!("Invalid {} \"{}\"", option, age))?;
+
+    Ok((ordering, number, unit))
+}
+
+// The comparison a leading "+" or "-" asks for, and what's left after it
+fn parse_ordering(value: &str) -> (Ordering, &str) {
+    match value.chars().next() {
+        Some('+') => (Ordering::Greater, &value[1..]),
+        Some('-') => (Ordering::Less, &value[1..]),
+        _ => (Ordering::Equal, value),
+    }
+}
+
+// A Regex matching the whole of a name just like the glob: "*" is any run
+// of characters, "?" any one, and "[...]" or "[!...]" a set of them
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                // An unmatched "[" is just a "[", as in the shell
+                let mut rest = chars.clone();
+                rest.next_if_eq(&'!');
+                rest.next_if_eq(&']');
+                if !rest.any(|c| c == ']') {
+                    pattern.push_str("\\[");
+                    continue;
+                }
+
+                pattern.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    pattern.push('^');
+                }
+                // A "]" right away is part of the set
+                if chars.next_if_eq(&']').is_some() {
+                    pattern.push_str("\\]");
+                }
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => break,
+                        '\\' | '[' | '&' | '~' => {
+                            pattern.push('\\');
+                            pattern.push(c)
+                        }
+                        _ => pattern.push(c),
+                    }
+                }
+                pattern.push(']');
+            }
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_to_regex;
+
+    #[test]
+    fn test_glob_to_regex() {
+        assert_eq!(glob_to_regex("*.rs"), "^.*\\.rs$");
+        assert_eq!(glob_to_regex("a?[!b-d]"), "^a.[^b-d]$");
+        assert_eq!(glob_to_regex("[]a]"), "^[\\]a]$");
+
+        // An unmatched "[" is taken literally
+        assert_eq!(glob_to_regex("a["), "^a\\[$");
+        assert_eq!(glob_to_regex("[!]"), "^\\[!\\]$");
+        assert_eq!(glob_to_regex("[a[b]"), "^[a\\[b]$");
+    }
+}
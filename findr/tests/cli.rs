@@ -2,6 +2,7 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
 use std::{borrow::Cow, fs, path::Path};
+use tempfile::TempDir;
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -39,7 +40,7 @@ fn skips_bad_dir() -> TestResult {
 #[test]
 fn dies_bad_name() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["--name", "*.csv"])
+        .args(["--name", "*.csv"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Invalid --name \"*.csv\""));
@@ -51,7 +52,7 @@ fn dies_bad_name() -> TestResult {
 fn dies_bad_type() -> TestResult {
     let expected = "error: 'x' isn't a valid value for '--type <TYPE>...'";
     Command::cargo_bin(PRG)?
-        .args(&["--type", "x"])
+        .args(["--type", "x"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -60,14 +61,14 @@ fn dies_bad_type() -> TestResult {
 
 // --------------------------------------------------
 #[cfg(windows)]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Owned(format!("{}.windows", expected_file))
     format!("{}.windows", expected_file).into()
 }
 
 // --------------------------------------------------
 #[cfg(not(windows))]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Borrowed(expected_file)
     expected_file.into()
 }
@@ -76,15 +77,13 @@ fn format_file_name(expected_file: &str) -> Cow<str> {
 fn run(args: &[&str], expected_file: &str) -> TestResult {
     let file = format_file_name(expected_file);
     let contents = fs::read_to_string(file.as_ref())?;
-    let mut expected: Vec<&str> =
-        contents.split("\n").filter(|s| !s.is_empty()).collect();
+    let mut expected: Vec<&str> = contents.split("\n").filter(|s| !s.is_empty()).collect();
     expected.sort();
 
     let cmd = Command::cargo_bin(PRG)?.args(args).assert().success();
     let out = cmd.get_output();
     let stdout = String::from_utf8(out.stdout.clone())?;
-    let mut lines: Vec<&str> =
-        stdout.split("\n").filter(|s| !s.is_empty()).collect();
+    let mut lines: Vec<&str> = stdout.split("\n").filter(|s| !s.is_empty()).collect();
     lines.sort();
 
     assert_eq!(lines, expected);
@@ -275,12 +274,484 @@ fn type_d_name_a() -> TestResult {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn iname_a() -> TestResult {
+    run(
+        &["tests/inputs", "--iname", "A"],
+        "tests/expected/name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn name_is_case_sensitive() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--name", "A"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tests/inputs").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_iname() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--iname", "*.CSV"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --iname \"*.CSV\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn glob_csv() -> TestResult {
+    run(
+        &["tests/inputs", "--glob", "-n", "*.csv"],
+        "tests/expected/name_csv.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn glob_matches_whole_name() -> TestResult {
+    run(
+        &["tests/inputs", "--glob", "--iname", "?.CSV", "-n", "*.mp3"],
+        "tests/expected/name_csv_mp3.txt",
+    )?;
+
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--glob", "-n", "csv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tests/inputs").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn path_glob() -> TestResult {
+    run(
+        &["tests/inputs", "--path", "*/[ad]/*.txt"],
+        "tests/expected/name_txt_path_a_d.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn regex_path() -> TestResult {
+    run(
+        &["tests/inputs", "--regex-path", "/[ad]/[^/]*[.]txt$"],
+        "tests/expected/name_txt_path_a_d.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regex_path() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--regex-path", "*.rs"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --regex-path \"*.rs\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn expr_or_not() -> TestResult {
+    run(
+        &[
+            "tests/inputs",
+            "(",
+            "-n",
+            "csv$",
+            "-o",
+            "-n",
+            "mp3$",
+            ")",
+            "!",
+            "--path",
+            "*/d/*",
+        ],
+        "tests/expected/expr_or_not.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune() -> TestResult {
+    // Neither b nor e is listed, nor anything below them
+    run(
+        &["tests/inputs", "--prune", "^b$", "--prune", "^e$"],
+        "tests/expected/prune.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune_not_paths() -> TestResult {
+    run(
+        &["tests/inputs/a/b", "--glob", "--prune", "?", "-t", "f"],
+        "tests/expected/prune_not_paths.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_precedence() -> TestResult {
+    // AND binds tighter than OR, and no directory is empty
+    run(
+        &[
+            "tests/inputs",
+            "-t",
+            "f",
+            "-a",
+            "!",
+            "-n",
+            "txt",
+            "-o",
+            "-t",
+            "d",
+            "--empty",
+        ],
+        "tests/expected/expr_precedence.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_repeated() -> TestResult {
+    // Repeats are fine with an operator, and tests of different kinds
+    // needn't have one
+    run(
+        &["tests/inputs", "(", "-n", "csv$", "-o", "-n", "mp3$", ")"],
+        "tests/expected/name_csv_mp3.txt",
+    )?;
+    run(
+        &["tests/inputs", "(", "-t", "f", "-n", "a", ")"],
+        "tests/expected/type_f_name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_expr() -> TestResult {
+    for (args, expected) in [
+        (&["(", "-n", "a"][..], "Missing \")\" in the expression"),
+        (&["-n", "a", ")"][..], "Unexpected \")\""),
+        (&["!", "--bogus"][..], "Unknown test \"--bogus\""),
+        (&["!", "-n"][..], "Missing value for \"-n\""),
+        (
+            &["(", "-n", "a", "--iname", "b", ")"][..],
+            "Missing -a or -o before the repeated \"--iname\"",
+        ),
+        (
+            &["-t", "f", "-t", "d", "-o", "--empty"][..],
+            "Missing -a or -o before the repeated \"-t\"",
+        ),
+    ] {
+        Command::cargo_bin(PRG)?
+            .arg("tests/inputs")
+            .args(args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(expected));
+    }
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 fn path_g() -> TestResult {
     run(&["tests/inputs/g.csv"], "tests/expected/path_g.txt")
 }
 
+// --------------------------------------------------
+#[test]
+fn dies_bad_size() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--size", "2x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --size \"2x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn size_bytes() -> TestResult {
+    run(
+        &["tests/inputs", "-t", "f", "--size", "2c"],
+        "tests/expected/type_f.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn size_blocks() -> TestResult {
+    // Two bytes round up to a whole 512-byte block
+    run(
+        &["tests/inputs", "-t", "f", "--size", "1"],
+        "tests/expected/type_f.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn size_less() -> TestResult {
+    // The link itself is bigger than the files
+    run(
+        &["tests/inputs", "-t", "f", "-t", "l", "--size", "-3c"],
+        "tests/expected/type_f.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn size_greater() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-t", "f", "--size", "+2c"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tests/inputs").not());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_mtime() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--mtime", "+1d"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid --mtime \"+1d\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_newer() -> TestResult {
+    let bad = gen_bad_file();
+    let expected = format!("{}: .* [(]os error 2[)]", &bad);
+    Command::cargo_bin(PRG)?
+        .args(["--newer", &bad])
+        .assert()
+        .failure()
+        .stderr(predicate::str::is_match(expected)?);
+    Ok(())
+}
+
+// --------------------------------------------------
+// A temporary directory holding the given files, or directories for the
+// names ending in a slash, removed along with them once dropped
+fn fixture(entries: &[(&str, &str)]) -> Result<TempDir, Box<dyn std::error::Error>> {
+    let dir = tempfile::tempdir()?;
+    for (name, contents) in entries {
+        let path = dir.path().join(name);
+        if name.ends_with('/') {
+            fs::create_dir_all(path)?;
+        } else {
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, contents)?;
+        }
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+// The paths found when run from dir, sorted, relative to it and with /
+// separators
+fn find_in(dir: &Path, args: &[&str]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let cmd = Command::cargo_bin(PRG)?
+        .current_dir(dir)
+        .args(args)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let top = dir.display().to_string();
+    let mut lines: Vec<String> = stdout
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.strip_prefix(&top) {
+            Some("") => ".".to_string(),
+            Some(rest) => rest[1..].replace('\\', "/"),
+            None => line.trim_start_matches("./").replace('\\', "/"),
+        })
+        .collect();
+    lines.sort();
+    Ok(lines)
+}
+
+// --------------------------------------------------
+#[test]
+fn modified() -> TestResult {
+    // A file from two days ago and another from just now
+    let dir = fixture(&[("new.txt", ""), ("old.txt", "")])?;
+    fs::File::options()
+        .write(true)
+        .open(dir.path().join("old.txt"))?
+        .set_modified(
+            std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 24 * 60 * 60),
+        )?;
+
+    let find = |args: &[&str]| find_in(dir.path(), &[&["-t", "f"], args].concat());
+    assert_eq!(find(&["--mtime", "+1"])?, ["old.txt"]);
+    assert_eq!(find(&["--mtime", "2"])?, ["old.txt"]);
+    assert_eq!(find(&["--mtime", "-1"])?, ["new.txt"]);
+    assert_eq!(find(&["--mmin", "-5"])?, ["new.txt"]);
+    assert_eq!(find(&["--mmin", "+60"])?, ["old.txt"]);
+    assert_eq!(find(&["--newer", "old.txt"])?, ["new.txt"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn empty() -> TestResult {
+    // None of the inputs are empty
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--empty"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("tests/inputs").not());
+
+    let dir = fixture(&[("empty/", ""), ("empty.txt", ""), ("full.txt", "full")])?;
+    assert_eq!(find_in(dir.path(), &["--empty"])?, ["empty", "empty.txt"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn hidden_and_ignored() -> TestResult {
+    let dir = fixture(&[
+        (".git/config", ""),
+        (".gitignore", "*.log\n!keep.log\nbuild/\n"),
+        (".hidden", ""),
+        ("a.txt", ""),
+        ("build/out", ""),
+        ("keep.log", ""),
+        ("x.log", ""),
+        ("sub/.gitignore", "a.txt\n"),
+        ("sub/a.txt", ""),
+    ])?;
+    let top = dir.path().display().to_string();
+    let find = |args: &[&str]| find_in(dir.path(), &[&[top.as_str()], args].concat());
+
+    assert_eq!(find(&[])?, [".", "a.txt", "keep.log", "sub"]);
+    assert_eq!(
+        find(&["--hidden"])?,
+        [
+            ".",
+            ".gitignore",
+            ".hidden",
+            "a.txt",
+            "keep.log",
+            "sub",
+            "sub/.gitignore"
+        ]
+    );
+    assert_eq!(
+        find(&["--no-ignore"])?,
+        [
+            ".",
+            "a.txt",
+            "build",
+            "build/out",
+            "keep.log",
+            "sub",
+            "sub/a.txt",
+            "x.log"
+        ]
+    );
+    assert_eq!(
+        find(&["--hidden", "--no-ignore", "-t", "f"])?,
+        [
+            ".git/config",
+            ".gitignore",
+            ".hidden",
+            "a.txt",
+            "build/out",
+            "keep.log",
+            "sub/.gitignore",
+            "sub/a.txt",
+            "x.log"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn ignored_from_above() -> TestResult {
+    // Searching a subdirectory still honors the ignore files of the
+    // repository it is in
+    let dir = fixture(&[
+        (".git/info/exclude", "excluded.txt\n"),
+        (".gitignore", "*.log\n/sub/deeper/\n"),
+        ("sub/a.log", ""),
+        ("sub/b.txt", ""),
+        ("sub/excluded.txt", ""),
+        ("sub/deeper/c.txt", ""),
+    ])?;
+    let sub = dir.path().join("sub").display().to_string();
+
+    assert_eq!(find_in(dir.path(), &["sub"])?, ["sub", "sub/b.txt"]);
+    assert_eq!(
+        find_in(dir.path(), &["sub", "--no-ignore"])?,
+        [
+            "sub",
+            "sub/a.log",
+            "sub/b.txt",
+            "sub/deeper",
+            "sub/deeper/c.txt",
+            "sub/excluded.txt"
+        ]
+    );
+    assert_eq!(find_in(dir.path(), &[&sub, "-n", "txt"])?, ["sub/b.txt"]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links() -> TestResult {
+    // real/x.txt, a link to real, a link back up to the top and one to
+    // nowhere
+    let dir = fixture(&[("real/x.txt", "x")])?;
+    std::os::unix::fs::symlink("real", dir.path().join("link"))?;
+    std::os::unix::fs::symlink("..", dir.path().join("real/up"))?;
+    std::os::unix::fs::symlink("nowhere", dir.path().join("broken"))?;
+    let top = dir.path().display().to_string();
+    let link = dir.path().join("link").display().to_string();
+    let find = |args: &[&str]| find_in(dir.path(), args);
+
+    assert_eq!(find(&[&top, "-t", "f"])?, ["real/x.txt"]);
+    assert_eq!(
+        find(&[&top, "-L", "-t", "f"])?,
+        ["link/x.txt", "real/x.txt"]
+    );
+    Command::cargo_bin(PRG)?
+        .args([&top, "-L", "-t", "f"])
+        .assert()
+        .stderr(predicate::str::contains("File system loop found"));
+    // As in find, followed links are what they point to, so only broken
+    // ones are links, and loops are reported rather than listed
+    assert_eq!(find(&[&top, "-L", "-t", "l"])?, ["broken"]);
+    assert!(find(&[&link, "-t", "f"])?.is_empty());
+    assert_eq!(find(&[&link, "-H", "-t", "f"])?, ["link/x.txt"]);
+    assert!(find(&[&link, "-L", "-P", "-t", "f"])?.is_empty());
+    assert_eq!(find(&[&top, "-t", "l"])?, ["broken", "link", "real/up"]);
+    assert_eq!(find(&[&top, "-L", "-t", "d"])?, [".", "link", "real"]);
+    // With -H only the path itself is what it points to
+    assert_eq!(find(&[&link, "-H", "-t", "l"])?, ["link/up"]);
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 #[cfg(not(windows))]
@@ -295,7 +766,7 @@ fn unreadable_dir() -> TestResult {
     //permissions.set_mode(0o000);
 
     std::process::Command::new("chmod")
-        .args(&["000", dirname])
+        .args(["000", dirname])
         .status()
         .expect("failed");
 
@@ -307,8 +778,7 @@ fn unreadable_dir() -> TestResult {
 
     let out = cmd.get_output();
     let stdout = String::from_utf8(out.stdout.clone())?;
-    let lines: Vec<&str> =
-        stdout.split("\n").filter(|s| !s.is_empty()).collect();
+    let lines: Vec<&str> = stdout.split("\n").filter(|s| !s.is_empty()).collect();
 
     assert_eq!(lines.len(), 17);
 
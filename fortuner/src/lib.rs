@@ -1,26 +1,114 @@
-use clap::{App, Arg};
+use clap::{App, Arg, SubCommand};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rand::distributions::WeightedIndex;
 use rand::prelude::*;
 use regex::{Regex, RegexBuilder};
+use serde_json::json;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
 use std::error::Error;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use walkdir::{DirEntry, WalkDir};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+// Where fortune databases usually get installed
+const SYSTEM_DIRS: &[&str] = &["/usr/share/games/fortunes", "/usr/share/fortune"];
+
+const ONE_DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Cache entries left unused for this long get removed
+const CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+// Size of the header strfile writes at the start of a .dat index
+const DAT_HEADER_LEN: usize = 24;
+
+// Flags in that header: offsets shuffled (-r) or sorted by text (-o), and
+// fortunes rot13-encoded (-x)
+const STR_RANDOM: u64 = 0x1;
+const STR_ORDERED: u64 = 0x2;
+const STR_ROTATED: u64 = 0x4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum Offensive {
+    #[default]
+    Exclude,
+    Only,
+    All,
+}
+
+// How directories get searched for fortune files
+#[derive(Debug, Default)]
+struct Walk {
+    offensive: Offensive,
+    max_depth: Option<usize>,
+    follow_links: bool,
+    file_pattern: Option<Regex>,
+    exclude: GlobSet,
+}
+
+#[derive(Debug, PartialEq)]
+struct Source {
+    path: String,
+    percent: Option<f64>,
+}
+
 #[derive(Debug)]
 pub struct Config {
-    sources: Vec<String>,
+    sources: Vec<Source>,
     pattern: Option<Regex>,
     seed: Option<u64>,
+    equal: bool,
+    show_source: bool,
+    walk: Walk,
+    list_files: bool,
+    count: usize,
+    delimiter: String,
+    rot13: bool,
+    stat: bool,
+    cache: Option<PathBuf>,
+    json: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Fortune {
     source: String,
     text: String,
+    // Decoded already, from a file its .dat marks as rot13-encoded
+    rotated: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    count: usize,
+    min: usize,
+    max: usize,
+    total: usize,
+    shortest: String,
+    longest: String,
+}
+
+#[derive(Debug)]
+struct FortuneIndex {
+    path: PathBuf,
+    source: String,
+    delim: String,
+    offsets: Vec<u64>,
+    rotated: bool,
+}
+
+// Fortunes left to pick from, either as offsets into indexed files or
+// parsed in full when some file has no index
+#[derive(Debug)]
+enum Pool {
+    Indexed(Vec<FortuneIndex>, Vec<(usize, u64)>),
+    Parsed(Vec<Fortune>),
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -31,10 +119,19 @@ pub fn get_args() -> MyResult<Config> {
         .arg(
             Arg::with_name("files")
                 .value_name("FILE")
-                .help("Input files or directories")
-                .required(true)
+                .help("Input files or directories [default: $FORTUNER_PATH or the system fortunes]")
                 .multiple(true),
         )
+        .subcommand(
+            SubCommand::with_name("stat")
+                .about("Show fortune counts and lengths for each file")
+                .arg(
+                    Arg::with_name("files")
+                        .value_name("FILE")
+                        .help("Input files or directories")
+                        .multiple(true),
+                ),
+        )
         .arg(
             Arg::with_name("pattern")
                 .value_name("PATTERN")
@@ -48,7 +145,7 @@ pub fn get_args() -> MyResult<Config> {
                 .value_name("SEED")
                 .short("s")
                 .long("seed")
-                .help("Random seed")
+                .help("Random seed, a number or any string")
                 .takes_value(true),
         )
         .arg(
@@ -57,9 +154,117 @@ pub fn get_args() -> MyResult<Config> {
                 .long("insensitive")
                 .help("Case-insensitive pattern matching"),
         )
+        .arg(
+            Arg::with_name("equal")
+                .short("e")
+                .long("equal")
+                .help("Give every source file an equal probability"),
+        )
+        .arg(
+            Arg::with_name("show_source")
+                .short("c")
+                .long("show-source")
+                .help("Show the file each fortune came from"),
+        )
+        .arg(
+            Arg::with_name("offensive")
+                .short("o")
+                .long("offensive")
+                .help("Choose only from offensive fortunes"),
+        )
+        .arg(
+            Arg::with_name("all")
+                .short("a")
+                .long("all")
+                .help("Choose from all fortunes, offensive or not")
+                .conflicts_with("offensive"),
+        )
+        .arg(
+            Arg::with_name("list_files")
+                .short("f")
+                .long("files")
+                .help("List the files and their chance of being picked")
+                .conflicts_with("pattern"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .value_name("N")
+                .long("count")
+                .help("Number of distinct fortunes to print")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("delimiter")
+                .value_name("STRING")
+                .long("delimiter")
+                .help("Line separating fortunes, empty for blank lines")
+                .takes_value(true)
+                .default_value("%"),
+        )
+        .arg(
+            Arg::with_name("rot13")
+                .short("x")
+                .long("rot13")
+                .help("Decode rot13-encoded fortunes"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print fortunes as JSON objects, one per line")
+                .conflicts_with("list_files"),
+        )
+        .arg(
+            Arg::with_name("no_cache")
+                .long("no-cache")
+                .help("Don't cache fortune offsets on disk"),
+        )
+        .arg(
+            Arg::with_name("file_pattern")
+                .value_name("REGEX")
+                .long("file-pattern")
+                .help("Only read files whose name matches REGEX")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .value_name("GLOB")
+                .long("exclude")
+                .help("Skip files and directories whose name matches GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("max_depth")
+                .value_name("N")
+                .long("max-depth")
+                .help("Descend at most N directory levels")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no_recurse")
+                .long("no-recurse")
+                .help("Only read the files directly inside directories")
+                .conflicts_with("max_depth"),
+        )
+        .arg(
+            Arg::with_name("follow_links")
+                .long("follow-links")
+                .help("Follow symbolic links into files and directories"),
+        )
         .get_matches();
 
-    let sources = matches.values_of_lossy("files").unwrap();
+    // "fortuner stat FILE..." takes its sources from the subcommand
+    let stat = matches.subcommand_matches("stat");
+    let files = stat
+        .unwrap_or(&matches)
+        .values_of_lossy("files")
+        .unwrap_or_else(|| default_sources(env::var_os("FORTUNER_PATH")));
+    if files.is_empty() {
+        return Err("No fortune databases found, pass FILE or set FORTUNER_PATH".into());
+    }
+    let sources = parse_sources(&files)?;
 
     let pattern = matches
         .value_of("pattern")
@@ -71,64 +276,535 @@ pub fn get_args() -> MyResult<Config> {
         })
         .transpose()?;
 
-    let seed = matches.value_of("seed").map(parse_u64).transpose()?;
+    let seed = matches.value_of("seed").map(parse_seed);
+
+    let equal = matches.is_present("equal");
+    let show_source = matches.is_present("show_source");
+
+    let offensive = if matches.is_present("offensive") {
+        Offensive::Only
+    } else if matches.is_present("all") {
+        Offensive::All
+    } else {
+        Offensive::Exclude
+    };
+
+    // Directory arguments sit at depth 0, so their files are at depth 1
+    let max_depth = if matches.is_present("no_recurse") {
+        Some(1)
+    } else {
+        matches
+            .value_of("max_depth")
+            .map(parse_u64)
+            .transpose()?
+            .map(|n| n as usize)
+    };
+
+    let file_pattern = matches
+        .value_of("file_pattern")
+        .map(|p| Regex::new(p).map_err(|_| format!("Invalid --file-pattern \"{}\"", p)))
+        .transpose()?;
+
+    let mut exclude = GlobSetBuilder::new();
+    for glob in matches.values_of_lossy("exclude").unwrap_or_default() {
+        exclude.add(Glob::new(&glob).map_err(|_| format!("Invalid --exclude \"{}\"", glob))?);
+    }
+
+    let walk = Walk {
+        offensive,
+        max_depth,
+        follow_links: matches.is_present("follow_links"),
+        file_pattern,
+        exclude: exclude.build()?,
+    };
+
+    let list_files = matches.is_present("list_files");
+
+    let count = matches
+        .value_of("count")
+        .map(parse_count)
+        .transpose()?
+        .unwrap();
+
+    let delimiter = matches.value_of("delimiter").unwrap().to_string();
+
+    let cache = if matches.is_present("no_cache") {
+        None
+    } else {
+        cache_dir()
+    };
 
     Ok(Config {
         sources,
         pattern,
         seed,
+        equal,
+        show_source,
+        walk,
+        list_files,
+        count,
+        delimiter,
+        rot13: matches.is_present("rot13"),
+        stat: stat.is_some(),
+        cache,
+        json: matches.is_present("json"),
     })
 }
 
+// Like $PATH, FORTUNER_PATH holds a list of directories. Without it, the
+// usual system directories are searched.
+fn default_sources(fortuner_path: Option<OsString>) -> Vec<String> {
+    match fortuner_path.filter(|path| !path.is_empty()) {
+        Some(path) => env::split_paths(&path)
+            .map(|dir| dir.to_string_lossy().into_owned())
+            .collect(),
+        None => SYSTEM_DIRS
+            .iter()
+            .filter(|dir| Path::new(dir).is_dir())
+            .map(|dir| dir.to_string())
+            .collect(),
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("fortuner"))
+}
+
 pub fn run(config: Config) -> MyResult<()> {
-    let files = find_files(&config.sources)?;
-    let fortunes = read_fortunes(&files)?;
+    // Each "N%" source gets its own share, and all the other sources
+    // split whatever is left
+    let mut groups = vec![];
+    let mut rest = vec![];
 
-    if let Some(pattern) = config.pattern {
-        let mut prev_source = None;
+    for source in &config.sources {
+        match source.percent {
+            Some(percent) => groups.push((
+                percent,
+                find_files(std::slice::from_ref(&source.path), &config.walk)?,
+            )),
+            None => rest.push(source.path.clone()),
+        }
+    }
+
+    let weighted: f64 = groups.iter().map(|(percent, _)| percent).sum();
+    groups.push((100.0 - weighted, find_files(&rest, &config.walk)?));
+
+    if config.stat {
+        for (_, files) in &groups {
+            for file in files {
+                let stats = file_stats(file, &config.delimiter, config.rot13)?;
+                print_stats(&file.display().to_string(), &stats, &mut io::stdout())?;
+            }
+        }
+
+        return Ok(());
+    }
 
-        for fortune in fortunes
+    if config.list_files {
+        let counts = groups
             .iter()
-            .filter(|fortune| pattern.is_match(&fortune.text))
-        {
-            if prev_source.as_ref().map_or(true, |s| s != &fortune.source) {
-                eprintln!("({})\n%", fortune.source);
-                prev_source = Some(fortune.source.clone());
+            .map(|(_, files)| count_fortunes(files, &config.delimiter, config.cache.as_deref()))
+            .collect::<MyResult<Vec<_>>>()?;
+        let percents: Vec<_> = groups.iter().map(|(percent, _)| *percent).collect();
+        let sizes: Vec<_> = counts.iter().map(|counts| counts.iter().sum()).collect();
+        let shares = group_shares(&percents, &sizes);
+
+        for (((_, files), counts), share) in groups.iter().zip(&counts).zip(shares) {
+            for (file, chance) in files.iter().zip(file_chances(counts, config.equal)) {
+                println!("{:>6.2}% {}", chance * share * 100.0, file.display());
             }
+        }
+
+        return Ok(());
+    }
+
+    if let Some(pattern) = config.pattern {
+        let mut files: Vec<_> = groups.into_iter().flat_map(|(_, files)| files).collect();
+        files.sort();
+        files.dedup();
+
+        let mut fortunes = read_fortunes(&files, &config.delimiter)?;
+        decode(&mut fortunes, config.rot13);
 
-            println!("{}\n%", fortune.text);
+        if config.json {
+            fortunes.retain(|fortune| pattern.is_match(&fortune.text));
+            print_json(&fortunes, &mut io::stdout())?;
+        } else {
+            print_matches(
+                &fortunes,
+                &pattern,
+                config.show_source,
+                &mut io::stdout(),
+                &mut io::stderr(),
+            )?;
         }
     } else {
-        let fortune = pick_fortune(&fortunes, config.seed)
-            .or_else(|| Some("No fortunes found".to_string()))
-            .unwrap();
+        let mut rng = make_rng(config.seed);
+
+        let mut fortunes: Vec<_> = if config.count == 1 {
+            random_fortune(
+                &groups,
+                &config.delimiter,
+                config.cache.as_deref(),
+                &mut rng,
+                config.equal,
+            )?
+            .into_iter()
+            .collect()
+        } else {
+            random_fortunes(
+                &groups,
+                config.count,
+                &config.delimiter,
+                config.cache.as_deref(),
+                &mut rng,
+                config.equal,
+            )?
+        };
+
+        decode(&mut fortunes, config.rot13);
+
+        if config.json {
+            return print_json(&fortunes, &mut io::stdout());
+        }
+
+        if fortunes.is_empty() {
+            println!("No fortunes found");
+        }
+
+        for (i, fortune) in fortunes.iter().enumerate() {
+            if i > 0 {
+                println!("%");
+            }
+
+            if config.show_source {
+                println!("({})\n%", fortune.source);
+            }
+
+            println!("{}", fortune.text);
+        }
+    }
+
+    Ok(())
+}
+
+// A single pick streams through the files, so only the chosen fortune
+// is ever kept in memory
+fn random_fortune(
+    groups: &[(f64, Vec<PathBuf>)],
+    delimiter: &str,
+    cache: Option<&Path>,
+    rng: &mut impl Rng,
+    equal: bool,
+) -> MyResult<Option<Fortune>> {
+    let mut percents: Vec<_> = groups.iter().map(|(percent, _)| *percent).collect();
+
+    loop {
+        // A group turns out to be empty only once it's been read, so its
+        // share goes away and another group gets picked
+        let group = if groups.len() == 1 {
+            0
+        } else {
+            match WeightedIndex::new(&percents) {
+                Ok(dist) => dist.sample(rng),
+                Err(_) => return Ok(None),
+            }
+        };
+
+        let files = &groups[group].1;
+        let fortune = match read_indexes(files, delimiter, cache) {
+            Some(indexes) => take_fortune(&mut indexed_pool(indexes), rng, equal)?,
+            None => stream_fortune(files, delimiter, rng, equal)?,
+        };
+
+        if fortune.is_some() || groups.len() == 1 {
+            return Ok(fortune);
+        }
+
+        percents[group] = 0.0;
+    }
+}
+
+fn random_fortunes(
+    groups: &[(f64, Vec<PathBuf>)],
+    count: usize,
+    delimiter: &str,
+    cache: Option<&Path>,
+    rng: &mut impl Rng,
+    equal: bool,
+) -> MyResult<Vec<Fortune>> {
+    let percents: Vec<_> = groups.iter().map(|(percent, _)| *percent).collect();
+    let mut pools = groups
+        .iter()
+        .map(|(_, files)| load_pool(files, delimiter, cache))
+        .collect::<MyResult<Vec<_>>>()?;
+
+    let mut fortunes = vec![];
+    while fortunes.len() < count {
+        // Shares follow the fortunes still left, so a group that runs
+        // out stops being picked
+        let group = if pools.len() == 1 {
+            Some(0)
+        } else {
+            let sizes: Vec<_> = pools.iter().map(pool_len).collect();
+            WeightedIndex::new(group_shares(&percents, &sizes))
+                .ok()
+                .map(|dist| dist.sample(rng))
+        };
+
+        match group {
+            Some(group) => match take_fortune(&mut pools[group], rng, equal)? {
+                Some(fortune) => fortunes.push(fortune),
+                None => break,
+            },
+            None => break,
+        }
+    }
+
+    Ok(fortunes)
+}
+
+fn parse_sources(args: &[String]) -> MyResult<Vec<Source>> {
+    let percent_re = Regex::new(r"^([0-9]+(?:\.[0-9]+)?)%(.*)$").unwrap();
+    let mut sources = vec![];
+    let mut percent = None;
+
+    for arg in args {
+        // The percentage is either its own argument or a prefix of the path
+        let (value, path) = match percent_re.captures(arg) {
+            Some(caps) => (Some(caps[1].parse::<f64>()?), caps[2].to_string()),
+            None => (None, arg.to_string()),
+        };
+
+        if let Some(value) = value {
+            if let Some(percent) = percent {
+                return Err(format!("Missing source after \"{}%\"", percent).into());
+            }
+            percent = Some(value);
+        }
+
+        if !path.is_empty() {
+            sources.push(Source {
+                path,
+                percent: percent.take(),
+            });
+        }
+    }
+
+    if let Some(percent) = percent {
+        return Err(format!("Missing source after \"{}%\"", percent).into());
+    }
+
+    let total: f64 = sources.iter().filter_map(|source| source.percent).sum();
+    if total > 100.0 {
+        return Err(format!("Percentages add up to {}%, more than 100%", total).into());
+    }
+
+    Ok(sources)
+}
+
+fn group_shares(percents: &[f64], sizes: &[usize]) -> Vec<f64> {
+    // A group without fortunes can't be picked, so its share goes away
+    let percents: Vec<_> = percents
+        .iter()
+        .zip(sizes)
+        .map(|(percent, &size)| if size > 0 { *percent } else { 0.0 })
+        .collect();
+    let total: f64 = percents.iter().sum();
+
+    percents
+        .iter()
+        .map(|percent| if total > 0.0 { percent / total } else { 0.0 })
+        .collect()
+}
+
+// Like fortune -m, each file with matches gets a "(file)" header on
+// stderr, and every matching fortune goes to stdout followed by a "%" line
+fn print_matches(
+    fortunes: &[Fortune],
+    pattern: &Regex,
+    show_source: bool,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> MyResult<()> {
+    let mut prev_source = None;
+
+    for fortune in fortunes
+        .iter()
+        .filter(|fortune| pattern.is_match(&fortune.text))
+    {
+        if prev_source != Some(&fortune.source) {
+            writeln!(err, "({})\n%", fortune.source)?;
+            prev_source = Some(&fortune.source);
+        }
+
+        if show_source {
+            writeln!(out, "({})\n%", fortune.source)?;
+        }
+
+        writeln!(out, "{}\n%", fortune.text)?;
+    }
+
+    Ok(())
+}
+
+// One JSON object per line, so scripts can read fortunes as they come
+fn print_json(fortunes: &[Fortune], out: &mut impl Write) -> MyResult<()> {
+    for fortune in fortunes {
+        let value = json!({
+            "source": fortune.source,
+            "text": fortune.text,
+            "length": fortune.text.chars().count(),
+        });
+        writeln!(out, "{}", value)?;
+    }
+
+    Ok(())
+}
+
+fn file_stats(path: &Path, delimiter: &str, decode: bool) -> MyResult<Stats> {
+    let mut stats = Stats::default();
+
+    // Those of a file marked as rot13-encoded are decoded already
+    let decode = decode && !is_rotated(path);
+    read_records(path, delimiter, |text| {
+        let len = text.chars().count();
+        let text = if decode { rot13(&text) } else { text };
+
+        if stats.count == 0 || len < stats.min {
+            stats.min = len;
+            stats.shortest = text.clone();
+        }
 
-        println!("{}", fortune);
+        if stats.count == 0 || len > stats.max {
+            stats.max = len;
+            stats.longest = text;
+        }
+
+        stats.count += 1;
+        stats.total += len;
+    })?;
+
+    Ok(stats)
+}
+
+fn print_stats(source: &str, stats: &Stats, out: &mut impl Write) -> MyResult<()> {
+    writeln!(out, "({})\nfortunes: {}", source, stats.count)?;
+
+    if stats.count > 0 {
+        let mean = stats.total as f64 / stats.count as f64;
+        writeln!(
+            out,
+            "length: min {}, mean {:.2}, max {}",
+            stats.min, mean, stats.max
+        )?;
+        writeln!(
+            out,
+            "shortest:\n{}\nlongest:\n{}",
+            stats.shortest, stats.longest
+        )?;
     }
 
+    writeln!(out, "%")?;
     Ok(())
 }
 
+// Decodes what -x asks to, leaving alone the fortunes decoded already
+fn decode(fortunes: &mut [Fortune], rot13_asked: bool) {
+    if rot13_asked {
+        for fortune in fortunes.iter_mut().filter(|fortune| !fortune.rotated) {
+            fortune.text = rot13(&fortune.text);
+        }
+    }
+}
+
+// Offensive databases are traditionally stored rot13-encoded
+fn rot13(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            'a'..='m' | 'A'..='M' => (c as u8 + 13) as char,
+            'n'..='z' | 'N'..='Z' => (c as u8 - 13) as char,
+            _ => c,
+        })
+        .collect()
+}
+
+// Numbers are used as is, anything else is hashed with FNV-1a so that a
+// seed like "lunch-talk" picks the same fortunes everywhere
+fn parse_seed(val: &str) -> u64 {
+    val.parse().unwrap_or_else(|_| {
+        val.bytes().fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+    })
+}
+
 fn parse_u64(val: &str) -> MyResult<u64> {
     val.parse()
         .map_err(|_| format!("\"{}\" not a valid integer", val).into())
 }
 
-fn find_files(paths: &[String]) -> MyResult<Vec<PathBuf>> {
+fn parse_count(val: &str) -> MyResult<usize> {
+    match val.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("\"{}\" not a valid count", val).into()),
+    }
+}
+
+fn is_offensive(root: &Path, path: &Path) -> bool {
+    // Offensive databases conventionally carry a "-o" suffix or live in an
+    // "off" directory, below the one searched as whoever named that one
+    // knows what is in it
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().ends_with("-o"))
+        || path
+            .strip_prefix(root)
+            .is_ok_and(|below| below.components().any(|c| c.as_os_str() == "off"))
+}
+
+fn find_files(paths: &[String], walk: &Walk) -> MyResult<Vec<PathBuf>> {
     let mut files = Vec::new();
 
-    let file_filter = |entry: &DirEntry| {
-        entry.file_type().is_file() && entry.path().extension() != Some(OsStr::new("dat"))
+    // Files named on the command line are always read
+    let file_filter = |root: &Path, entry: &DirEntry| {
+        entry.file_type().is_file()
+            && entry.path().extension() != Some(OsStr::new("dat"))
+            && walk
+                .file_pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(&entry.file_name().to_string_lossy()))
+            && (entry.depth() == 0
+                || match walk.offensive {
+                    Offensive::Exclude => !is_offensive(root, entry.path()),
+                    Offensive::Only => is_offensive(root, entry.path()),
+                    Offensive::All => true,
+                })
     };
 
     for path in paths {
+        if path == "-" {
+            files.push(PathBuf::from(path));
+            continue;
+        }
+
         match fs::metadata(path) {
             Err(e) => return Err(format!("{}: {}", path, e).into()),
             Ok(_) => files.extend(
                 WalkDir::new(path)
+                    .max_depth(walk.max_depth.unwrap_or(usize::MAX))
+                    .follow_links(walk.follow_links)
                     .into_iter()
+                    // Excluding a directory skips everything below it, but
+                    // paths given on the command line are always read
+                    .filter_entry(|entry| {
+                        entry.depth() == 0 || !walk.exclude.is_match(entry.file_name())
+                    })
                     .filter_map(Result::ok)
-                    .filter(file_filter)
+                    .filter(|entry| file_filter(Path::new(path), entry))
                     .map(|entry| entry.path().into()),
             ),
         }
@@ -139,118 +815,665 @@ fn find_files(paths: &[String]) -> MyResult<Vec<PathBuf>> {
     Ok(files)
 }
 
-fn read_fortunes(paths: &[PathBuf]) -> MyResult<Vec<Fortune>> {
-    let mut buf = vec![];
-    let mut fortunes = vec![];
+fn open(path: &Path) -> MyResult<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
 
-    for path in paths {
-        let basename = path.file_name().unwrap().to_string_lossy().into_owned();
-        let file = File::open(path).map_err(|e| format!("{}: {}", &basename, e))?;
+// Calls f with the text of every fortune in the file, one at a time,
+// decoded if its .dat says it is rot13-encoded
+fn read_records(path: &Path, delimiter: &str, mut f: impl FnMut(String)) -> MyResult<()> {
+    let basename = path.file_name().unwrap().to_string_lossy().into_owned();
+    let file = open(path).map_err(|e| format!("{}: {}", &basename, e))?;
+    let rotated = path != Path::new("-") && is_rotated(path);
+    let mut buf = vec![];
 
-        for line in BufReader::new(file).lines().filter_map(Result::ok) {
-            if line == "%" {
+    // A file may end without a delimiter after its last fortune
+    for line in lines(file).map(Some).chain([None]) {
+        match line {
+            Some(line) if line != delimiter => buf.push(line),
+            _ => {
                 if !buf.is_empty() {
-                    let source = basename.clone();
                     let text = buf.join("\n");
-
-                    fortunes.push(Fortune { source, text });
+                    f(if rotated { rot13(&text) } else { text });
                     buf.clear();
                 }
-            } else {
-                buf.push(line.to_string());
             }
         }
     }
 
-    Ok(fortunes)
+    Ok(())
 }
 
-fn pick_fortune(fortunes: &[Fortune], seed: Option<u64>) -> Option<String> {
-    if let Some(s) = seed {
-        let mut rng = rand::rngs::StdRng::seed_from_u64(s);
-        fortunes
-            .choose(&mut rng)
-            .map(|fortune| fortune.text.to_string())
-    } else {
-        let mut rng = rand::thread_rng();
-        fortunes
-            .choose(&mut rng)
-            .map(|fortune| fortune.text.to_string())
-    }
+// The lines read, each decoded on its own so that one which isn't UTF-8
+// doesn't end the file
+fn lines(reader: impl BufRead) -> impl Iterator<Item = String> {
+    reader.split(b'\n').map_while(Result::ok).map(|mut line| {
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        String::from_utf8_lossy(&line).into_owned()
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{find_files, parse_u64, pick_fortune, read_fortunes, Fortune};
-    use std::path::PathBuf;
-
-    #[test]
-    fn test_parse_u64() {
-        let res = parse_u64("a");
-        assert!(res.is_err());
-        assert_eq!(res.unwrap_err().to_string(), "\"a\" not a valid integer");
+fn read_fortunes(paths: &[PathBuf], delimiter: &str) -> MyResult<Vec<Fortune>> {
+    let mut fortunes = vec![];
 
-        let res = parse_u64("0");
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), 0);
+    for path in paths {
+        let basename = path.file_name().unwrap().to_string_lossy().into_owned();
 
-        let res = parse_u64("4");
-        assert!(res.is_ok());
-        assert_eq!(res.unwrap(), 4);
+        let rotated = is_rotated(path);
+        read_records(path, delimiter, |text| {
+            fortunes.push(Fortune {
+                source: basename.clone(),
+                text,
+                rotated,
+            })
+        })?;
     }
 
-    #[test]
-    fn test_find_files() {
-        // Verify that the function finds a file known to exist
-        let res = find_files(&["./tests/inputs/jokes".to_string()]);
-        assert!(res.is_ok());
+    Ok(fortunes)
+}
 
-        let files = res.unwrap();
-        assert_eq!(files.len(), 1);
-        assert_eq!(
-            files.get(0).unwrap().to_string_lossy(),
-            "./tests/inputs/jokes"
-        );
+fn count_fortunes(
+    paths: &[PathBuf],
+    delimiter: &str,
+    cache: Option<&Path>,
+) -> MyResult<Vec<usize>> {
+    match read_indexes(paths, delimiter, cache) {
+        Some(indexes) => Ok(indexes.iter().map(|index| index.offsets.len()).collect()),
+        None => paths
+            .iter()
+            .map(|path| {
+                let mut count = 0;
+                read_records(path, delimiter, |_| count += 1).map(|_| count)
+            })
+            .collect(),
+    }
+}
 
-        // Fails to find a bad file
-        let res = find_files(&["/path/does/not/exist".to_string()]);
-        assert!(res.is_err());
+// Reservoir sampling: the n-th fortune of a file replaces the one kept
+// so far with a probability of 1/n. Files are then sampled the same way,
+// weighted by their number of fortunes unless every file counts equally.
+fn stream_fortune(
+    paths: &[PathBuf],
+    delimiter: &str,
+    rng: &mut impl Rng,
+    equal: bool,
+) -> MyResult<Option<Fortune>> {
+    let mut chosen = None;
+    let mut total = 0;
 
-        // Finds all the input files, excludes ".dat"
-        let res = find_files(&["./tests/inputs".to_string()]);
-        assert!(res.is_ok());
+    for path in paths {
+        let mut text = None;
+        let mut count = 0;
 
-        // Check number and order of files
-        let files = res.unwrap();
-        assert_eq!(files.len(), 5);
-        let first = files.get(0).unwrap().display().to_string();
-        assert!(first.contains("ascii-art"));
-        let last = files.last().unwrap().display().to_string();
-        assert!(last.contains("quotes"));
+        read_records(path, delimiter, |record| {
+            count += 1;
+            if rng.gen_range(0..count) == 0 {
+                text = Some(record);
+            }
+        })?;
 
-        // Test for multiple sources, path must be unique and sorted
-        let res = find_files(&[
-            "./tests/inputs/jokes".to_string(),
-            "./tests/inputs/ascii-art".to_string(),
-            "./tests/inputs/jokes".to_string(),
-        ]);
-        assert!(res.is_ok());
+        let weight = match count {
+            0 => continue,
+            _ if equal => 1,
+            _ => count,
+        };
 
-        let files = res.unwrap();
-        assert_eq!(files.len(), 2);
-        if let Some(filename) = files.first().unwrap().file_name() {
-            assert_eq!(filename.to_string_lossy(), "ascii-art".to_string())
+        total += weight;
+        if rng.gen_range(0..total) < weight {
+            chosen = text.map(|text| Fortune {
+                source: path.file_name().unwrap().to_string_lossy().into_owned(),
+                text,
+                rotated: is_rotated(path),
+            });
+        }
+    }
+
+    Ok(chosen)
+}
+
+fn file_chances(counts: &[usize], equal: bool) -> Vec<f64> {
+    let total: usize = counts.iter().sum();
+    let nonempty = counts.iter().filter(|&&count| count > 0).count();
+
+    counts
+        .iter()
+        .map(|&count| match count {
+            0 => 0.0,
+            _ if equal => 1.0 / nonempty as f64,
+            _ => count as f64 / total as f64,
+        })
+        .collect()
+}
+
+fn read_indexes(
+    paths: &[PathBuf],
+    delimiter: &str,
+    cache: Option<&Path>,
+) -> Option<Vec<FortuneIndex>> {
+    // strfile only knows single character delimiters, so an index built
+    // for another one can't be trusted
+    paths
+        .iter()
+        .map(|path| {
+            read_index(path)
+                .filter(|index| index.offsets.is_empty() || index.delim == delimiter)
+                .or_else(|| cache.and_then(|cache| cached_index(path, delimiter, cache)))
+        })
+        .collect()
+}
+
+// Files without an up to date .dat get their offsets cached on disk, keyed
+// by path, size and mtime, so only changed files get parsed again
+fn cached_index(path: &Path, delimiter: &str, cache: &Path) -> Option<FortuneIndex> {
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let canonical = fs::canonicalize(path).ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    (&canonical, delimiter).hash(&mut hasher);
+    let entry = cache.join(format!("{:016x}", hasher.finish()));
+    let key = format!(
+        "{:?} {} {} {:?}",
+        canonical,
+        metadata.len(),
+        mtime.as_nanos(),
+        delimiter
+    );
+
+    // The key goes on the first line, followed by one offset per line
+    let contents = fs::read_to_string(&entry).unwrap_or_default();
+    let mut lines = contents.lines();
+    let offsets = match lines.next() {
+        Some(line) if line == key => {
+            touch_cache(&entry);
+            lines.map(|line| line.parse().ok()).collect::<Option<_>>()?
+        }
+        _ => {
+            let offsets = scan_offsets(path, delimiter).ok()?;
+
+            // Failing to write the cache only means parsing again next time
+            let _ = write_cache(&entry, &key, &offsets);
+            prune_cache(cache);
+            offsets
+        }
+    };
+
+    Some(FortuneIndex {
+        path: path.into(),
+        source: path.file_name()?.to_string_lossy().into_owned(),
+        delim: delimiter.to_string(),
+        offsets,
+        rotated: is_rotated(path),
+    })
+}
+
+fn scan_offsets(path: &Path, delimiter: &str) -> io::Result<Vec<u64>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut offsets = vec![];
+    let mut line = vec![];
+    let mut pos = 0;
+    let mut in_fortune = false;
+
+    // A fortune starts at the first line that isn't a delimiter
+    loop {
+        line.clear();
+        let bytes = file.read_until(b'\n', &mut line)?;
+        if bytes == 0 {
+            break;
+        }
+
+        let text = line.strip_suffix(b"\n").unwrap_or(&line);
+        let text = text.strip_suffix(b"\r").unwrap_or(text);
+        if text == delimiter.as_bytes() {
+            in_fortune = false;
+        } else if !in_fortune {
+            offsets.push(pos);
+            in_fortune = true;
+        }
+
+        pos += bytes as u64;
+    }
+
+    Ok(offsets)
+}
+
+fn write_cache(entry: &Path, key: &str, offsets: &[u64]) -> io::Result<()> {
+    let mut contents = format!("{}\n", key);
+    for offset in offsets {
+        contents.push_str(&format!("{}\n", offset));
+    }
+
+    // Write somewhere else first so a reader never sees half an entry, and
+    // somewhere of its own so another fortuner doesn't write there too
+    let tmp = entry.with_extension(format!("{}.tmp", process::id()));
+    fs::create_dir_all(entry.parent().unwrap())?;
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, entry)
+}
+
+// Entries get their mtime bumped once a day at most when used, so that it
+// tells how long they have gone unused
+fn touch_cache(entry: &Path) {
+    let now = SystemTime::now();
+    let stale = fs::metadata(entry)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|mtime| now.duration_since(mtime).unwrap_or_default() > ONE_DAY);
+    if stale {
+        let _ = File::options()
+            .write(true)
+            .open(entry)
+            .and_then(|file| file.set_modified(now));
+    }
+}
+
+// Removes the entries, and temporary files, of fortune files that are
+// gone or haven't been read in a while
+fn prune_cache(cache: &Path) {
+    let now = SystemTime::now();
+    for entry in fs::read_dir(cache).into_iter().flatten().flatten() {
+        let old = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|mtime| now.duration_since(mtime).unwrap_or_default() > CACHE_MAX_AGE);
+        if old {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+fn read_index(path: &Path) -> Option<FortuneIndex> {
+    let source = path.file_name()?.to_string_lossy().into_owned();
+    let len = fs::metadata(path).ok()?.len();
+
+    if len == 0 {
+        return Some(FortuneIndex {
+            path: path.into(),
+            source,
+            delim: "%".to_string(),
+            offsets: vec![],
+            rotated: false,
+        });
+    }
+
+    let mut dat = path.as_os_str().to_owned();
+    dat.push(".dat");
+
+    let buf = fs::read(dat).ok()?;
+    let word = |i: usize| {
+        buf.get(i * 4..i * 4 + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as u64)
+    };
+
+    // The header holds the number of fortunes, flags and the delimiter,
+    // followed by one offset per fortune plus a final one at the end of the
+    // file
+    let num_fortunes = word(1)? as usize;
+    let flags = word(4)?;
+    let delim = (*buf.get(DAT_HEADER_LEN - 4)? as char).to_string();
+    let mut offsets: Vec<_> = (0..=num_fortunes)
+        .map(|i| word(DAT_HEADER_LEN / 4 + i))
+        .collect::<Option<_>>()?;
+
+    // Everything else expects offsets in the order of the file
+    if flags & (STR_RANDOM | STR_ORDERED) != 0 {
+        offsets.sort_unstable();
+    }
+
+    // An index that doesn't end where the file does is stale
+    if offsets.last() != Some(&len) {
+        return None;
+    }
+
+    Some(FortuneIndex {
+        path: path.into(),
+        source,
+        delim,
+        offsets: offsets[..num_fortunes].to_vec(),
+        rotated: flags & STR_ROTATED != 0,
+    })
+}
+
+// Whether the .dat of a file, up to date or not, says its fortunes are
+// rot13-encoded
+fn is_rotated(path: &Path) -> bool {
+    let mut dat = path.as_os_str().to_owned();
+    dat.push(".dat");
+
+    let mut header = [0; DAT_HEADER_LEN];
+    File::open(dat)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|_| {
+            u32::from_be_bytes([header[16], header[17], header[18], header[19]]) as u64
+                & STR_ROTATED
+                != 0
+        })
+}
+
+fn read_fortune_at(index: &FortuneIndex, offset: u64) -> MyResult<Fortune> {
+    let mut file = File::open(&index.path).map_err(|e| format!("{}: {}", &index.source, e))?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let text = lines(BufReader::new(file))
+        .take_while(|line| line != &index.delim)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = if index.rotated { rot13(&text) } else { text };
+
+    Ok(Fortune {
+        source: index.source.clone(),
+        text,
+        rotated: index.rotated,
+    })
+}
+
+fn load_pool(files: &[PathBuf], delimiter: &str, cache: Option<&Path>) -> MyResult<Pool> {
+    // With an index for every file, only the chosen fortunes get read
+    match read_indexes(files, delimiter, cache) {
+        Some(indexes) => Ok(indexed_pool(indexes)),
+        None => Ok(Pool::Parsed(read_fortunes(files, delimiter)?)),
+    }
+}
+
+fn indexed_pool(indexes: Vec<FortuneIndex>) -> Pool {
+    let entries = indexes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, index)| index.offsets.iter().map(move |&offset| (i, offset)))
+        .collect();
+    Pool::Indexed(indexes, entries)
+}
+
+fn pool_len(pool: &Pool) -> usize {
+    match pool {
+        Pool::Indexed(_, entries) => entries.len(),
+        Pool::Parsed(fortunes) => fortunes.len(),
+    }
+}
+
+// Picks a fortune and removes it from the pool, so the same fortune is
+// never picked twice
+fn take_fortune(pool: &mut Pool, rng: &mut impl Rng, equal: bool) -> MyResult<Option<Fortune>> {
+    match pool {
+        Pool::Indexed(indexes, entries) => {
+            match choose_position(entries, |&(i, _)| i, rng, equal) {
+                Some(pos) => {
+                    let (i, offset) = entries.remove(pos);
+                    read_fortune_at(&indexes[i], offset).map(Some)
+                }
+                None => Ok(None),
+            }
+        }
+        Pool::Parsed(fortunes) => {
+            Ok(
+                choose_position(fortunes, |fortune| fortune.source.as_str(), rng, equal)
+                    .map(|pos| fortunes.remove(pos)),
+            )
+        }
+    }
+}
+
+fn choose_position<'a, T, K: PartialEq>(
+    items: &'a [T],
+    source: impl Fn(&'a T) -> K,
+    rng: &mut impl Rng,
+    equal: bool,
+) -> Option<usize> {
+    let chosen = choose(items, source, rng, equal)?;
+    items.iter().position(|item| std::ptr::eq(item, chosen))
+}
+
+fn make_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(s) => StdRng::seed_from_u64(s),
+        None => StdRng::from_entropy(),
+    }
+}
+
+fn choose<'a, T, K: PartialEq>(
+    items: &'a [T],
+    source: impl Fn(&'a T) -> K,
+    rng: &mut impl Rng,
+    equal: bool,
+) -> Option<&'a T> {
+    if equal {
+        // Pick a source first so that every file is equally likely,
+        // no matter how many fortunes it holds
+        let mut sources: Vec<_> = items.iter().map(&source).collect();
+        sources.dedup();
+
+        let chosen = sources.choose(rng)?;
+        let candidates: Vec<_> = items
+            .iter()
+            .filter(|&item| source(item) == *chosen)
+            .collect();
+
+        candidates.choose(rng).copied()
+    } else {
+        // Every fortune is equally likely, which weights each file by the
+        // number of fortunes it holds
+        items.choose(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cached_index, default_sources, file_chances, file_stats, find_files, load_pool, make_rng,
+        parse_count, parse_seed, parse_sources, parse_u64, pool_len, print_json, print_matches,
+        print_stats, read_fortune_at, read_fortunes, read_index, rot13, stream_fortune,
+        take_fortune, Fortune, Offensive, Pool, Source, Walk, CACHE_MAX_AGE, STR_RANDOM,
+        STR_ROTATED,
+    };
+    use globset::{Glob, GlobSetBuilder};
+    use regex::Regex;
+    use std::fs::{self, File};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_parse_u64() {
+        let res = parse_u64("a");
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().to_string(), "\"a\" not a valid integer");
+
+        let res = parse_u64("0");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), 0);
+
+        let res = parse_u64("4");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), 4);
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        // Numbers keep working as before
+        assert_eq!(parse_seed("0"), 0);
+        assert_eq!(parse_seed("4"), 4);
+
+        // Anything else hashes to the same seed every time
+        assert_eq!(parse_seed(""), 0xcbf29ce484222325);
+        assert_eq!(parse_seed("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(parse_seed("lunch-talk"), parse_seed("lunch-talk"));
+        assert_ne!(parse_seed("lunch-talk"), parse_seed("lunch-talks"));
+    }
+
+    #[test]
+    fn test_default_sources() {
+        assert_eq!(
+            default_sources(Some("./tests/inputs:/usr/share/fortune".into())),
+            vec!["./tests/inputs", "/usr/share/fortune"]
+        );
+
+        // An empty variable is the same as none at all
+        assert_eq!(default_sources(Some("".into())), default_sources(None));
+    }
+
+    #[test]
+    fn test_find_files() {
+        // Verify that the function finds a file known to exist
+        let res = find_files(&["./tests/inputs/jokes".to_string()], &Walk::default());
+        assert!(res.is_ok());
+
+        let files = res.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files.first().unwrap().to_string_lossy(),
+            "./tests/inputs/jokes"
+        );
+
+        // Fails to find a bad file
+        let res = find_files(&["/path/does/not/exist".to_string()], &Walk::default());
+        assert!(res.is_err());
+
+        // Finds all the input files, excludes ".dat"
+        let res = find_files(&["./tests/inputs".to_string()], &Walk::default());
+        assert!(res.is_ok());
+
+        // Check number and order of files
+        let files = res.unwrap();
+        assert_eq!(files.len(), 5);
+        let first = files.first().unwrap().display().to_string();
+        assert!(first.contains("ascii-art"));
+        let last = files.last().unwrap().display().to_string();
+        assert!(last.contains("quotes"));
+
+        // Test for multiple sources, path must be unique and sorted
+        let res = find_files(
+            &[
+                "./tests/inputs/jokes".to_string(),
+                "./tests/inputs/ascii-art".to_string(),
+                "./tests/inputs/jokes".to_string(),
+            ],
+            &Walk::default(),
+        );
+        assert!(res.is_ok());
+
+        let files = res.unwrap();
+        assert_eq!(files.len(), 2);
+        if let Some(filename) = files.first().unwrap().file_name() {
+            assert_eq!(filename.to_string_lossy(), "ascii-art".to_string())
         }
 
         if let Some(filename) = files.last().unwrap().file_name() {
             assert_eq!(filename.to_string_lossy(), "jokes".to_string())
         }
+
+        // Standard input is passed through as is
+        let res = find_files(
+            &["-".to_string(), "./tests/inputs/jokes".to_string()],
+            &Walk::default(),
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            vec![PathBuf::from("./tests/inputs/jokes"), PathBuf::from("-")]
+        );
+
+        // Offensive databases are only found when asked for
+        let res = find_files(
+            &["./tests/inputs".to_string()],
+            &Walk {
+                offensive: Offensive::Only,
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let files = res.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files.first().unwrap().to_string_lossy(),
+            "./tests/inputs/off/riddles"
+        );
+
+        let res = find_files(
+            &["./tests/inputs".to_string()],
+            &Walk {
+                offensive: Offensive::All,
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().len(), 6);
+
+        // Limiting the depth skips the nested databases
+        let res = find_files(
+            &["./tests/inputs".to_string()],
+            &Walk {
+                offensive: Offensive::All,
+                max_depth: Some(1),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().len(), 4);
+
+        // Symbolic links are only followed when asked for
+        let res = find_files(&["./tests/links".to_string()], &Walk::default());
+        assert!(res.is_ok());
+        assert!(res.unwrap().is_empty());
+
+        let res = find_files(
+            &["./tests/links".to_string()],
+            &Walk {
+                follow_links: true,
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), vec![PathBuf::from("./tests/links/jokes")]);
+
+        // Only file names matching the pattern are kept
+        let res = find_files(
+            &["./tests/inputs".to_string()],
+            &Walk {
+                file_pattern: Some(Regex::new("^(jokes|quotes)$").unwrap()),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            vec![
+                PathBuf::from("./tests/inputs/jokes"),
+                PathBuf::from("./tests/inputs/quotes")
+            ]
+        );
+
+        // Excluded names are skipped, directories along with their files
+        let mut exclude = GlobSetBuilder::new();
+        exclude.add(Glob::new("*-art").unwrap());
+        exclude.add(Glob::new("off").unwrap());
+        let res = find_files(
+            &[
+                "./tests/inputs".to_string(),
+                "./tests/inputs/ascii-art".to_string(),
+            ],
+            &Walk {
+                offensive: Offensive::All,
+                exclude: exclude.build().unwrap(),
+                ..Default::default()
+            },
+        );
+        assert!(res.is_ok());
+
+        let files = res.unwrap();
+        assert_eq!(files.len(), 5);
+        assert!(files.contains(&PathBuf::from("./tests/inputs/ascii-art")));
+        assert!(!files.iter().any(|file| file.ends_with("riddles")));
     }
 
     #[test]
     fn test_read_fortunes() {
         // Parses all the fortunes without a filter
-        let res = read_fortunes(&[PathBuf::from("./tests/inputs/jokes")]);
+        let res = read_fortunes(&[PathBuf::from("./tests/inputs/jokes")], "%");
         assert!(res.is_ok());
 
         if let Ok(fortunes) = res {
@@ -269,38 +1492,516 @@ mod tests {
         }
 
         // Filters for matching text
-        let res = read_fortunes(&[
-            PathBuf::from("./tests/inputs/jokes"),
-            PathBuf::from("./tests/inputs/quotes"),
-        ]);
+        let res = read_fortunes(
+            &[
+                PathBuf::from("./tests/inputs/jokes"),
+                PathBuf::from("./tests/inputs/quotes"),
+            ],
+            "%",
+        );
         assert!(res.is_ok());
         assert_eq!(res.unwrap().len(), 11);
+
+        // Blank lines as the delimiter, with no trailing one
+        let res = read_fortunes(&[PathBuf::from("./tests/delimited/proverbs")], "");
+        assert!(res.is_ok());
+
+        let fortunes = res.unwrap();
+        assert_eq!(fortunes.len(), 3);
+        assert_eq!(
+            fortunes.last().unwrap().text,
+            "Where there is smoke,\nthere is fire."
+        );
+
+        // Longer delimiters
+        let res = read_fortunes(&[PathBuf::from("./tests/delimited/sayings")], "%%");
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap().len(), 2);
     }
 
     #[test]
     fn test_pick_fortune() {
-        // Create a slice of fortunes
-        let fortunes = &[
+        // Create a pool of fortunes
+        let fortunes = vec![
             Fortune {
                 source: "fortunes".to_string(),
                 text: "You cannot achieve the impossible without \
                       attempting the absurd."
                     .to_string(),
+                rotated: false,
             },
             Fortune {
                 source: "fortunes".to_string(),
                 text: "Assumption is the mother of all screw-ups.".to_string(),
+                rotated: false,
             },
             Fortune {
                 source: "fortunes".to_string(),
                 text: "Neckties strangle clear thinking.".to_string(),
+                rotated: false,
             },
         ];
 
         // Pick a fortune with a seed
         assert_eq!(
-            pick_fortune(fortunes, Some(1)).unwrap(),
+            take_fortune(&mut Pool::Parsed(fortunes), &mut make_rng(Some(1)), false)
+                .unwrap()
+                .unwrap()
+                .text,
             "Neckties strangle clear thinking.".to_string()
         );
     }
+
+    #[test]
+    fn test_pick_fortune_equal() {
+        // One source with a single fortune and another one with nine
+        let mut fortunes = vec![Fortune {
+            source: "small".to_string(),
+            text: "small".to_string(),
+            rotated: false,
+        }];
+        fortunes.extend((0..9).map(|i| Fortune {
+            source: "large".to_string(),
+            text: format!("large {}", i),
+            rotated: false,
+        }));
+
+        let small = |equal| {
+            (0..1000)
+                .filter(|&seed| {
+                    let mut pool = Pool::Parsed(fortunes.clone());
+                    take_fortune(&mut pool, &mut make_rng(Some(seed)), equal)
+                        .unwrap()
+                        .unwrap()
+                        .text
+                        == "small"
+                })
+                .count()
+        };
+
+        // Weighted by fortune count, the small source is rarely picked
+        assert!(small(false) < 200);
+
+        // With equal weighting, both sources get about half of the picks
+        let picks = small(true);
+        assert!(picks > 400 && picks < 600);
+    }
+
+    #[test]
+    fn test_read_index() {
+        let res = read_index(Path::new("./tests/inputs/jokes"));
+        assert!(res.is_some());
+
+        let index = res.unwrap();
+        assert_eq!(index.source, "jokes");
+        assert_eq!(index.delim, "%");
+        assert_eq!(index.offsets.len(), 6);
+        assert_eq!(index.offsets.first(), Some(&0));
+
+        // No index next to the file
+        assert!(read_index(Path::new("./tests/inputs/off/riddles")).is_none());
+
+        // Empty files don't need an index
+        let res = read_index(Path::new("./tests/inputs/empty/.gitkeep"));
+        assert!(res.is_some());
+        assert!(res.unwrap().offsets.is_empty());
+    }
+
+    #[test]
+    fn test_read_index_flags() {
+        // "a" and "b" rot13-encoded, with their offsets shuffled
+        let dir = std::env::temp_dir().join(format!("fortuner-flags-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("rotated");
+        fs::write(&path, "n\n%\no\n%\n").unwrap();
+        let mut dat = vec![];
+        for word in [2, 2, 1, 1, STR_RANDOM | STR_ROTATED] {
+            dat.extend_from_slice(&(word as u32).to_be_bytes());
+        }
+        dat.extend_from_slice(b"%\0\0\0");
+        for offset in [4u32, 0, 8] {
+            dat.extend_from_slice(&offset.to_be_bytes());
+        }
+        fs::write(dir.join("rotated.dat"), dat).unwrap();
+
+        let index = read_index(&path).unwrap();
+        let text = read_fortune_at(&index, index.offsets[0]).unwrap().text;
+        let fortunes = read_fortunes(&[path], "%").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(index.offsets, vec![0, 4]);
+        assert_eq!(text, "a");
+        assert_eq!(
+            fortunes
+                .iter()
+                .map(|fortune| fortune.text.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_line() {
+        // The line that isn't UTF-8 mustn't stop the reading
+        let dir = std::env::temp_dir().join(format!("fortuner-utf8-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bad");
+        fs::write(&path, b"one\n%\n\xff\n%\nthree\nfour\n%\n").unwrap();
+        let mut dat = vec![];
+        for word in [2, 3, 10, 1, 0] {
+            dat.extend_from_slice(&(word as u32).to_be_bytes());
+        }
+        dat.extend_from_slice(b"%\0\0\0");
+        for offset in [0u32, 6, 10, 23] {
+            dat.extend_from_slice(&offset.to_be_bytes());
+        }
+        fs::write(dir.join("bad.dat"), dat).unwrap();
+
+        let index = read_index(&path).unwrap();
+        let bad = read_fortune_at(&index, 6).unwrap().text;
+        let text = read_fortune_at(&index, 10).unwrap().text;
+        let fortunes = read_fortunes(&[path], "%").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(bad, "\u{fffd}");
+        assert_eq!(text, "three\nfour");
+        assert_eq!(
+            fortunes
+                .iter()
+                .map(|fortune| fortune.text.as_str())
+                .collect::<Vec<_>>(),
+            ["one", "\u{fffd}", "three\nfour"]
+        );
+    }
+
+    #[test]
+    fn test_cached_index() {
+        let cache = std::env::temp_dir().join(format!("fortuner-test-{}", std::process::id()));
+        let path = Path::new("./tests/delimited/proverbs");
+
+        // The first lookup parses the file and fills the cache
+        let index = cached_index(path, "", &cache).unwrap();
+        assert_eq!(index.offsets, vec![0, 30, 53]);
+        assert_eq!(
+            read_fortune_at(&index, 53).unwrap().text,
+            "Where there is smoke,\nthere is fire."
+        );
+
+        let entries: Vec<_> = fs::read_dir(&cache).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        // Later lookups come from the cache
+        let entry = entries[0].as_ref().unwrap().path();
+        let contents = fs::read_to_string(&entry).unwrap();
+        assert!(contents.ends_with("\n0\n30\n53\n"));
+        assert_eq!(
+            cached_index(path, "", &cache).unwrap().offsets,
+            index.offsets
+        );
+
+        // Another delimiter gets its own entry
+        assert_eq!(cached_index(path, "%", &cache).unwrap().offsets, vec![0]);
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 2);
+
+        // Standard input can't be indexed
+        assert!(cached_index(Path::new("-"), "%", &cache).is_none());
+
+        // Entries unused for too long go the next time one gets written
+        let old = SystemTime::now() - CACHE_MAX_AGE - Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&entry)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        fs::write(cache.join("gone"), "").unwrap();
+        File::options()
+            .write(true)
+            .open(cache.join("gone"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        cached_index(Path::new("./tests/inputs/jokes"), "%", &cache).unwrap();
+        assert!(!cache.join("gone").exists());
+        assert!(!entry.exists());
+        assert_eq!(fs::read_dir(&cache).unwrap().count(), 2);
+
+        fs::remove_dir_all(&cache).unwrap();
+    }
+
+    #[test]
+    fn test_pick_indexed() {
+        let paths = [
+            PathBuf::from("./tests/inputs/jokes"),
+            PathBuf::from("./tests/inputs/quotes"),
+        ];
+        let fortunes = read_fortunes(&paths, "%").unwrap();
+
+        // An index built for another delimiter is ignored
+        assert!(matches!(
+            load_pool(&paths, "%%", None).unwrap(),
+            Pool::Parsed(..)
+        ));
+
+        // Reading through the index picks the same fortunes as parsing
+        for seed in 0..20 {
+            for equal in [false, true] {
+                let mut pool = load_pool(&paths, "%", None).unwrap();
+                assert!(matches!(pool, Pool::Indexed(..)));
+
+                let indexed = take_fortune(&mut pool, &mut make_rng(Some(seed)), equal)
+                    .unwrap()
+                    .unwrap();
+                let parsed = take_fortune(
+                    &mut Pool::Parsed(fortunes.clone()),
+                    &mut make_rng(Some(seed)),
+                    equal,
+                )
+                .unwrap()
+                .unwrap();
+                assert_eq!(indexed.source, parsed.source);
+                assert_eq!(indexed.text, parsed.text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_take_fortune() {
+        let mut pool = load_pool(&[PathBuf::from("./tests/inputs/jokes")], "%", None).unwrap();
+        assert_eq!(pool_len(&pool), 6);
+
+        // Every fortune comes out exactly once before the pool runs dry
+        let mut rng = make_rng(Some(1));
+        let mut texts = vec![];
+        while let Some(fortune) = take_fortune(&mut pool, &mut rng, false).unwrap() {
+            texts.push(fortune.text);
+        }
+        assert_eq!(texts.len(), 6);
+        texts.sort();
+        texts.dedup();
+        assert_eq!(texts.len(), 6);
+        assert_eq!(pool_len(&pool), 0);
+    }
+
+    #[test]
+    fn test_stream_fortune() {
+        let paths = [
+            PathBuf::from("./tests/inputs/jokes"),
+            PathBuf::from("./tests/inputs/off/riddles"),
+        ];
+
+        let picks = |equal| {
+            (0..1000)
+                .map(|seed| {
+                    stream_fortune(&paths, "%", &mut make_rng(Some(seed)), equal)
+                        .unwrap()
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Every fortune can be picked, and riddles get 2 out of 8 picks
+        let fortunes = picks(false);
+        let mut texts: Vec<_> = fortunes.iter().map(|f| f.text.as_str()).collect();
+        texts.sort();
+        texts.dedup();
+        assert_eq!(texts.len(), 8);
+
+        let riddles = fortunes.iter().filter(|f| f.source == "riddles").count();
+        assert!(riddles > 150 && riddles < 350);
+
+        // With equal weighting, both files get about half of the picks
+        let riddles = picks(true).iter().filter(|f| f.source == "riddles").count();
+        assert!(riddles > 400 && riddles < 600);
+
+        // Nothing to pick from
+        let res = stream_fortune(
+            &[PathBuf::from("./tests/inputs/empty/.gitkeep")],
+            "%",
+            &mut make_rng(Some(1)),
+            false,
+        );
+        assert!(res.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rot13() {
+        assert_eq!(rot13("Uryyb, Jbeyq!"), "Hello, World!");
+        assert_eq!(
+            rot13(&rot13("Öne line, four wordś.")),
+            "Öne line, four wordś."
+        );
+        assert_eq!(rot13("0-9 %"), "0-9 %");
+    }
+
+    #[test]
+    fn test_print_json() {
+        let fortunes = [
+            Fortune {
+                source: "quotes".to_string(),
+                text: "It's like deja vu all over again.\n-- Yogi Berra".to_string(),
+                rotated: false,
+            },
+            Fortune {
+                source: "wordś".to_string(),
+                text: "\"Öne\"\ttab".to_string(),
+                rotated: false,
+            },
+        ];
+
+        let mut out = vec![];
+        assert!(print_json(&fortunes, &mut out).is_ok());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"length\":47,\"source\":\"quotes\",\
+            \"text\":\"It's like deja vu all over again.\\n-- Yogi Berra\"}\n\
+            {\"length\":9,\"source\":\"wordś\",\"text\":\"\\\"Öne\\\"\\ttab\"}\n"
+        );
+
+        let mut out = vec![];
+        assert!(print_json(&[], &mut out).is_ok());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_file_stats() {
+        let res = file_stats(Path::new("./tests/inputs/jokes"), "%", false);
+        assert!(res.is_ok());
+
+        let stats = res.unwrap();
+        assert_eq!(stats.count, 6);
+        assert_eq!(stats.min, 60);
+        assert_eq!(stats.max, 99);
+        assert_eq!(stats.total, 471);
+        assert_eq!(
+            stats.shortest,
+            "Q: What happens when frogs park illegally?\nA: They get toad."
+        );
+
+        let mut out = vec![];
+        assert!(print_stats("jokes", &stats, &mut out).is_ok());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "(jokes)\nfortunes: 6\nlength: min 60, mean 78.50, max 99\n\
+            shortest:\nQ: What happens when frogs park illegally?\nA: They get toad.\n\
+            longest:\nQ. Why did the honeydew couple get married in a church?\n\
+            A. Their parents told them they cantaloupe.\n%\n"
+        );
+
+        // Nothing but the count for empty files
+        let res = file_stats(Path::new("./tests/inputs/empty/.gitkeep"), "%", false);
+        assert_eq!(res.unwrap(), Default::default());
+
+        let mut out = vec![];
+        assert!(print_stats(".gitkeep", &Default::default(), &mut out).is_ok());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "(.gitkeep)\nfortunes: 0\n%\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("3").unwrap(), 3);
+
+        let res = parse_count("0");
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().to_string(), "\"0\" not a valid count");
+
+        assert!(parse_count("-1").is_err());
+        assert!(parse_count("foo").is_err());
+    }
+
+    #[test]
+    fn test_file_chances() {
+        // Files are weighted by the number of fortunes they hold
+        assert_eq!(file_chances(&[1, 3, 0], false), vec![0.25, 0.75, 0.0]);
+
+        // Unless all files with fortunes are made equally likely
+        assert_eq!(file_chances(&[1, 3, 0], true), vec![0.5, 0.5, 0.0]);
+
+        // Nothing to pick from
+        assert_eq!(file_chances(&[0], false), vec![0.0]);
+        assert!(file_chances(&[], true).is_empty());
+    }
+
+    #[test]
+    fn test_print_matches() {
+        let fortunes = read_fortunes(
+            &[
+                PathBuf::from("./tests/inputs/literature"),
+                PathBuf::from("./tests/inputs/quotes"),
+            ],
+            "%",
+        )
+        .unwrap();
+        let pattern = Regex::new("Yogi Berra|Mark Twain, ").unwrap();
+
+        let mut out = vec![];
+        let mut err = vec![];
+        let res = print_matches(&fortunes, &pattern, false, &mut out, &mut err);
+        assert!(res.is_ok());
+
+        // One header per file with matches
+        assert_eq!(
+            String::from_utf8(err).unwrap(),
+            "(literature)\n%\n(quotes)\n%\n"
+        );
+
+        // Each match is terminated by a "%" line
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "A classic is something that everyone wants to have read\n\
+            and nobody wants to read.\n\
+            \t\t-- Mark Twain, \"The Disappearance of Literature\"\n%\n\
+            It's like deja vu all over again.\n-- Yogi Berra\n%\n\
+            You can observe a lot just by watching.\n-- Yogi Berra\n%\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_sources() {
+        let args = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let source = |path: &str, percent| Source {
+            path: path.to_string(),
+            percent,
+        };
+
+        // Plain sources have no percentage
+        let res = parse_sources(&args(&["jokes", "quotes"]));
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            vec![source("jokes", None), source("quotes", None)]
+        );
+
+        // A percentage may be its own argument or a prefix
+        let res = parse_sources(&args(&["50%", "jokes", "25.5%quotes", "literature"]));
+        assert!(res.is_ok());
+        assert_eq!(
+            res.unwrap(),
+            vec![
+                source("jokes", Some(50.0)),
+                source("quotes", Some(25.5)),
+                source("literature", None)
+            ]
+        );
+
+        // A percentage must be followed by a source
+        let res = parse_sources(&args(&["jokes", "50%"]));
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().to_string(), "Missing source after \"50%\"");
+
+        let res = parse_sources(&args(&["50%", "20%", "jokes"]));
+        assert!(res.is_err());
+        assert_eq!(res.unwrap_err().to_string(), "Missing source after \"50%\"");
+
+        // Percentages can't exceed the whole
+        let res = parse_sources(&args(&["60%", "jokes", "50%", "quotes"]));
+        assert!(res.is_err());
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "Percentages add up to 110%, more than 100%"
+        );
+    }
 }
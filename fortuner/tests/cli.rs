@@ -12,6 +12,18 @@ const JOKES: &str = "./tests/inputs/jokes";
 const LITERATURE: &str = "./tests/inputs/literature";
 const QUOTES: &str = "./tests/inputs/quotes";
 
+// --------------------------------------------------
+// fortuner with its offsets cached somewhere temporary rather than in the
+// cache directory of whoever runs the tests
+fn fortuner() -> Result<Command, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(PRG)?;
+    cmd.env(
+        "XDG_CACHE_HOME",
+        std::env::temp_dir().join("fortuner-cli-cache"),
+    );
+    Ok(cmd)
+}
+
 // --------------------------------------------------
 fn random_string() -> String {
     rand::thread_rng()
@@ -36,8 +48,8 @@ fn gen_bad_file() -> String {
 fn dies_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
-    Command::cargo_bin(PRG)?
-        .args(&[LITERATURE, &bad])
+    fortuner()?
+        .args([LITERATURE, &bad])
         .assert()
         .failure()
         .stderr(predicate::str::is_match(expected)?);
@@ -46,24 +58,16 @@ fn dies_bad_file() -> TestResult {
 
 // --------------------------------------------------
 #[test]
-fn dies_bad_seed() -> TestResult {
-    let bad = random_string();
-    let expected = format!("\"{}\" not a valid integer", &bad);
-    Command::cargo_bin(PRG)?
-        .args(&[LITERATURE, "--seed", &bad])
-        .assert()
-        .failure()
-        .stderr(predicate::str::contains(expected));
-    Ok(())
+fn string_seed() -> TestResult {
+    run(
+        &[FORTUNE_DIR, "--seed", "lunch-talk"],
+        "A is for Apple.\n\t\t-- Hester Pryne\n",
+    )
 }
 
 // --------------------------------------------------
 fn run(args: &[&str], expected: &'static str) -> TestResult {
-    Command::cargo_bin(PRG)?
-        .args(args)
-        .assert()
-        .success()
-        .stdout(expected);
+    fortuner()?.args(args).assert().success().stdout(expected);
     Ok(())
 }
 
@@ -101,11 +105,517 @@ fn dir_seed_10() -> TestResult {
     )
 }
 
+// --------------------------------------------------
+#[test]
+fn equal_seed_1() -> TestResult {
+    run(
+        &[JOKES, QUOTES, "-e", "-s", "1"],
+        "Keep away from people who try to belittle your ambitions. \
+        Small people always do that, but the really great make you feel \
+        that you, too, can become great.\n-- Mark Twain\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn show_source_seed_1() -> TestResult {
+    run(
+        &[QUOTES, "-c", "-s", "1"],
+        "(quotes)\n%\nYou can observe a lot just by watching.\n-- Yogi Berra\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn show_source_pattern() -> TestResult {
+    fortuner()?
+        .args([LITERATURE, QUOTES, "-c", "-m", "Yogi Berra"])
+        .assert()
+        .success()
+        .stderr("(quotes)\n%\n")
+        .stdout(
+            "(quotes)\n%\nIt's like deja vu all over again.\n-- Yogi Berra\n%\n\
+            (quotes)\n%\nYou can observe a lot just by watching.\n-- Yogi Berra\n%\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn offensive_seed_1() -> TestResult {
+    run(
+        &[FORTUNE_DIR, "-o", "-s", "1"],
+        "What gets wetter the more it dries?\nA towel.\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn offensive_root() -> TestResult {
+    // Only directories below the one searched make fortunes offensive, and
+    // files named on the command line are always read
+    let riddle = "What gets wetter the more it dries?\nA towel.\n";
+    run(&["./tests/inputs/off", "-s", "1"], riddle)?;
+    run(&["./tests/inputs/off/riddles", "-s", "1"], riddle)
+}
+
+// --------------------------------------------------
+#[test]
+fn rot13_marked() -> TestResult {
+    // A file its .dat marks as rot13-encoded is decoded, with -x or not
+    let dir = std::env::temp_dir().join(format!("fortuner-{}", random_string()));
+    fs::create_dir_all(&dir)?;
+    let file = dir.join("rotated");
+    fs::write(&file, "Uryyb\n%\n")?;
+    let mut dat = vec![];
+    for word in [2u32, 1, 5, 5, 4] {
+        dat.extend_from_slice(&word.to_be_bytes());
+    }
+    dat.extend_from_slice(b"%\0\0\0");
+    for offset in [0u32, 8] {
+        dat.extend_from_slice(&offset.to_be_bytes());
+    }
+    fs::write(dir.join("rotated.dat"), dat)?;
+
+    let file = file.display().to_string();
+    let results = [
+        fortuner()?.arg(&file).output(),
+        fortuner()?.args([&file, "-x"]).output(),
+        fortuner()?.args([&file, "-x", "-m", "Hello"]).output(),
+    ];
+    fs::remove_dir_all(&dir)?;
+
+    for result in results {
+        let stdout = String::from_utf8(result?.stdout)?;
+        assert!(stdout.starts_with("Hello\n"), "{:?}", stdout);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_offensive_and_all() -> TestResult {
+    fortuner()?
+        .args([FORTUNE_DIR, "-o", "-a"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The argument '--offensive' cannot be used with '--all'",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn list_files() -> TestResult {
+    run(
+        &[JOKES, QUOTES, LITERATURE, "-f"],
+        " 40.00% ./tests/inputs/jokes\n \
+         26.67% ./tests/inputs/literature\n \
+         33.33% ./tests/inputs/quotes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn list_files_equal() -> TestResult {
+    run(
+        &[JOKES, QUOTES, EMPTY_DIR, "-f", "-e"],
+        "  0.00% ./tests/inputs/empty/.gitkeep\n \
+         50.00% ./tests/inputs/jokes\n \
+         50.00% ./tests/inputs/quotes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_seed_1() -> TestResult {
+    let input = fs::read_to_string(QUOTES)?;
+    fortuner()?
+        .args(["-", "-s", "1", "-c"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout(
+            "(-)\n%\nKeep away from people who try to belittle your ambitions. \
+            Small people always do that, but the really great make you feel \
+            that you, too, can become great.\n-- Mark Twain\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_pattern() -> TestResult {
+    let input = fs::read_to_string(QUOTES)?;
+    fortuner()?
+        .args(["-m", "Yogi Berra", "-", LITERATURE])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stderr("(-)\n%\n")
+        .stdout(
+            "It's like deja vu all over again.\n-- Yogi Berra\n%\n\
+            You can observe a lot just by watching.\n-- Yogi Berra\n%\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn list_files_weighted() -> TestResult {
+    run(
+        &["-f", "80%", JOKES, QUOTES, LITERATURE],
+        " 80.00% ./tests/inputs/jokes\n  \
+         8.89% ./tests/inputs/literature\n \
+         11.11% ./tests/inputs/quotes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn weighted_seed_1() -> TestResult {
+    // All of the weight goes to the jokes
+    run(
+        &["100%", JOKES, QUOTES, "-c", "-s", "1"],
+        "(jokes)\n%\nQ: What happens when frogs park illegally?\nA: They get toad.\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_dangling_percent() -> TestResult {
+    fortuner()?
+        .args([JOKES, "50%"])
+        .assert()
+        .failure()
+        .stderr("Missing source after \"50%\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_seed_1() -> TestResult {
+    run(
+        &[JOKES, "--count", "3", "-s", "1"],
+        "Q: What happens when frogs park illegally?\nA: They get toad.\n%\n\
+         Q. Why did the honeydew couple get married in a church?\n\
+         A. Their parents told them they cantaloupe.\n%\n\
+         Q: What do you call a deer wearing an eye patch?\n\
+         A: A bad idea (bad-eye deer).\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn count_more_than_available() -> TestResult {
+    // Only six jokes, each printed once
+    let output = fortuner()?
+        .args([JOKES, "--count", "10", "-s", "1"])
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let mut jokes: Vec<_> = stdout.split("%\n").collect();
+    assert_eq!(jokes.len(), 6);
+    jokes.sort();
+    jokes.dedup();
+    assert_eq!(jokes.len(), 6);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_count() -> TestResult {
+    fortuner()?
+        .args([JOKES, "--count", "0"])
+        .assert()
+        .failure()
+        .stderr("\"0\" not a valid count\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delimiter_blank_lines() -> TestResult {
+    run(
+        &[
+            "./tests/delimited/proverbs",
+            "--delimiter",
+            "",
+            "--count",
+            "3",
+            "-s",
+            "1",
+        ],
+        "Where there is smoke,\nthere is fire.\n%\n\
+         Look before you leap.\n%\n\
+         A stitch in time saves nine.\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn delimiter_pattern() -> TestResult {
+    fortuner()?
+        .args([
+            "./tests/delimited/sayings",
+            "--delimiter",
+            "%%",
+            "-m",
+            "waste",
+        ])
+        .assert()
+        .success()
+        .stderr("(sayings)\n%\n")
+        .stdout("Haste makes waste.\n%\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn max_depth() -> TestResult {
+    run(
+        &[FORTUNE_DIR, "-a", "-f", "--max-depth", "1"],
+        " 21.05% ./tests/inputs/ascii-art\n \
+         31.58% ./tests/inputs/jokes\n \
+         21.05% ./tests/inputs/literature\n \
+         26.32% ./tests/inputs/quotes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_links() -> TestResult {
+    run(
+        &["./tests/links", "-f", "--follow-links"],
+        "100.00% ./tests/links/jokes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_max_depth() -> TestResult {
+    fortuner()?
+        .args([FORTUNE_DIR, "--max-depth", "foo"])
+        .assert()
+        .failure()
+        .stderr("\"foo\" not a valid integer\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn rot13_seed_1() -> TestResult {
+    fortuner()?
+        .args(["-", "-x", "-s", "1"])
+        .write_stdin("Jung trgf jrggre gur zber vg qevrf?\nN gbjry.\n%\n")
+        .assert()
+        .success()
+        .stdout("What gets wetter the more it dries?\nA towel.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn rot13_pattern() -> TestResult {
+    // Matching happens on the decoded text
+    fortuner()?
+        .args(["-", "-x", "-m", "towel"])
+        .write_stdin("Jung trgf jrggre gur zber vg qevrf?\nN gbjry.\n%\n")
+        .assert()
+        .success()
+        .stdout("What gets wetter the more it dries?\nA towel.\n%\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stat() -> TestResult {
+    run(
+        &["stat", LITERATURE, EMPTY_DIR],
+        "(./tests/inputs/empty/.gitkeep)\nfortunes: 0\n%\n\
+        (./tests/inputs/literature)\nfortunes: 4\n\
+        length: min 33, mean 94.00, max 135\n\
+        shortest:\nA is for Apple.\n\t\t-- Hester Pryne\n\
+        longest:\nA banker is a fellow who lends you his umbrella when the sun is shining\n\
+        and wants it back the minute it begins to rain.\n\t\t-- Mark Twain\n%\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn cache_offsets() -> TestResult {
+    let cache = std::env::temp_dir().join(format!("fortuner-{}", random_string()));
+    let riddles = "./tests/inputs/off/riddles";
+
+    // Nothing gets cached when asked not to
+    fortuner()?
+        .args([riddles, "-o", "-s", "1", "--no-cache"])
+        .env("XDG_CACHE_HOME", &cache)
+        .assert()
+        .success();
+    assert!(fs::metadata(&cache).is_err());
+
+    // The same fortune comes out with a cold and a warm cache
+    for _ in 0..2 {
+        fortuner()?
+            .args([riddles, "-o", "-s", "1"])
+            .env("XDG_CACHE_HOME", &cache)
+            .assert()
+            .success()
+            .stdout("What gets wetter the more it dries?\nA towel.\n");
+        assert_eq!(fs::read_dir(cache.join("fortuner"))?.count(), 1);
+    }
+
+    fs::remove_dir_all(&cache)?;
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_seed_1() -> TestResult {
+    run(
+        &[QUOTES, "-s", "1", "--json"],
+        "{\"length\":53,\"source\":\"quotes\",\
+        \"text\":\"You can observe a lot just by watching.\\n-- Yogi Berra\"}\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn json_pattern() -> TestResult {
+    fortuner()?
+        .args([FORTUNE_DIR, "-m", "Yogi Berra", "--json"])
+        .assert()
+        .success()
+        .stderr("")
+        .stdout(
+            "{\"length\":47,\"source\":\"quotes\",\
+            \"text\":\"It's like deja vu all over again.\\n-- Yogi Berra\"}\n\
+            {\"length\":53,\"source\":\"quotes\",\
+            \"text\":\"You can observe a lot just by watching.\\n-- Yogi Berra\"}\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json_no_fortunes() -> TestResult {
+    run(&[EMPTY_DIR, "--json"], "")
+}
+
+// --------------------------------------------------
+#[test]
+fn file_pattern() -> TestResult {
+    run(
+        &[FORTUNE_DIR, "-f", "--file-pattern", "ke"],
+        "  0.00% ./tests/inputs/empty/.gitkeep\n\
+         100.00% ./tests/inputs/jokes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_file_pattern() -> TestResult {
+    fortuner()?
+        .args([FORTUNE_DIR, "--file-pattern", "*"])
+        .assert()
+        .failure()
+        .stderr("Invalid --file-pattern \"*\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fortuner_path() -> TestResult {
+    fortuner()?
+        .args(["-s", "1"])
+        .env("FORTUNER_PATH", format!("{}:{}", QUOTES, EMPTY_DIR))
+        .assert()
+        .success()
+        .stdout("You can observe a lot just by watching.\n-- Yogi Berra\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_no_sources() -> TestResult {
+    // Nothing to fall back on with an empty FORTUNER_PATH and no system
+    // fortunes installed
+    if ["/usr/share/games/fortunes", "/usr/share/fortune"]
+        .iter()
+        .any(|dir| fs::metadata(dir).is_ok())
+    {
+        return Ok(());
+    }
+
+    fortuner()?
+        .env("FORTUNER_PATH", "")
+        .assert()
+        .failure()
+        .stderr("No fortune databases found, pass FILE or set FORTUNER_PATH\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_recurse() -> TestResult {
+    run(
+        &[FORTUNE_DIR, "-a", "-f", "--no-recurse"],
+        " 21.05% ./tests/inputs/ascii-art\n \
+         31.58% ./tests/inputs/jokes\n \
+         21.05% ./tests/inputs/literature\n \
+         26.32% ./tests/inputs/quotes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_no_recurse_and_max_depth() -> TestResult {
+    fortuner()?
+        .args([FORTUNE_DIR, "--no-recurse", "--max-depth", "2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "The argument '--max-depth <N>' cannot be used with '--no-recurse'",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn exclude() -> TestResult {
+    run(
+        &[
+            FORTUNE_DIR,
+            "-f",
+            "--exclude",
+            "*-art",
+            "--exclude",
+            "[lq]*",
+        ],
+        "  0.00% ./tests/inputs/empty/.gitkeep\n\
+         100.00% ./tests/inputs/jokes\n",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_exclude() -> TestResult {
+    fortuner()?
+        .args([FORTUNE_DIR, "--exclude", "[a"])
+        .assert()
+        .failure()
+        .stderr("Invalid --exclude \"[a\"\n");
+    Ok(())
+}
+
 // --------------------------------------------------
 fn run_outfiles(args: &[&str], out_file: &str, err_file: &str) -> TestResult {
     let out = fs::read_to_string(out_file)?;
     let err = fs::read_to_string(err_file)?;
-    Command::cargo_bin(PRG)?
+    fortuner()?
         .args(args)
         .assert()
         .success()
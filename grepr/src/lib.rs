@@ -1,19 +1,79 @@
-use clap::{App, Arg};
-use regex::{Regex, RegexBuilder};
+use clap::{App, Arg, ErrorKind};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use memmap2::Mmap;
+use regex::{Captures, Regex, RegexBuilder};
+use std::collections::VecDeque;
+use std::env;
 use std::error::Error;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader};
-use walkdir::WalkDir;
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, LineWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+use walkdir::{DirEntry, WalkDir};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+// Files this big get mapped into memory instead of read line by line
+const MMAP_MIN_LEN: u64 = 1 << 20;
+
 #[derive(Debug)]
 pub struct Config {
     pattern: Regex,
     files: Vec<String>,
     recursive: bool,
+    walk: Walk,
     count: bool,
+    count_total: bool,
+    replace: Option<String>,
     invert_match: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    with_filename: Option<bool>,
+    before: usize,
+    after: usize,
+    quiet: bool,
+    binary_files: BinaryFiles,
+    colors: Colors,
+    eol: u8,
+    null: bool,
+    whole_buffer: bool,
+    label: String,
+    line_buffered: bool,
+    no_messages: bool,
+}
+
+// Big files are mapped and searched as a whole, everything else is read a
+// line at a time
+enum Input {
+    Stream(Box<dyn BufRead>),
+    Mapped(Mmap),
+}
+
+// SGR sequences as found in GREP_COLORS, an empty one meaning no color
+#[derive(Debug, Default, PartialEq)]
+struct Colors {
+    matched: String,
+    filename: String,
+    separator: String,
+}
+
+// Which files and directories -r looks at
+#[derive(Debug, Default)]
+struct Walk {
+    include: GlobSet,
+    exclude: GlobSet,
+    exclude_dir: GlobSet,
+    follow_links: bool,
+    gitignore: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum BinaryFiles {
+    Binary,
+    Text,
+    WithoutMatch,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -40,6 +100,19 @@ pub fn get_args() -> MyResult<Config> {
                 .long("count")
                 .help("Count occurrences"),
         )
+        .arg(
+            Arg::with_name("replace")
+                .value_name("TEMPLATE")
+                .long("replace")
+                .help("Print matching lines with the matches replaced by TEMPLATE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("count_total")
+                .long("count-total")
+                .help("Count occurrences across all files")
+                .conflicts_with_all(&["files_with_matches", "files_without_match"]),
+        )
         .arg(
             Arg::with_name("insensitive")
                 .short("i")
@@ -52,18 +125,204 @@ pub fn get_args() -> MyResult<Config> {
                 .long("invert-match")
                 .help("Invert match"),
         )
+        .arg(
+            Arg::with_name("word_regexp")
+                .short("w")
+                .long("word-regexp")
+                .help("Only match whole words"),
+        )
+        .arg(
+            Arg::with_name("line_regexp")
+                .short("x")
+                .long("line-regexp")
+                .help("Only match whole lines"),
+        )
+        .arg(
+            Arg::with_name("files_with_matches")
+                .short("l")
+                .long("files-with-matches")
+                .help("Print only names of files with matches"),
+        )
+        .arg(
+            Arg::with_name("files_without_match")
+                .short("L")
+                .long("files-without-match")
+                .help("Print only names of files without matches")
+                .conflicts_with("files_with_matches"),
+        )
+        .arg(
+            Arg::with_name("with_filename")
+                .short("H")
+                .long("with-filename")
+                .help("Print the file name for each match")
+                .overrides_with("no_filename"),
+        )
+        .arg(
+            Arg::with_name("no_filename")
+                .short("h")
+                .long("no-filename")
+                .help("Never print file names with matches")
+                .overrides_with("with_filename"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Print nothing, exit with success on the first match"),
+        )
+        .arg(
+            Arg::with_name("text")
+                .short("a")
+                .long("text")
+                .help("Search binary files as if they were text"),
+        )
+        .arg(
+            Arg::with_name("binary_files")
+                .value_name("TYPE")
+                .long("binary-files")
+                .help("How to search binary files")
+                .possible_values(&["binary", "text", "without-match"])
+                .default_value("binary")
+                .overrides_with("text"),
+        )
+        .arg(
+            Arg::with_name("no_messages")
+                .short("s")
+                .long("no-messages")
+                .help("Suppress error messages about unreadable files"),
+        )
+        .arg(
+            Arg::with_name("line_buffered")
+                .long("line-buffered")
+                .help("Flush output after every line"),
+        )
+        .arg(
+            Arg::with_name("label")
+                .value_name("NAME")
+                .long("label")
+                .help("Name to show for standard input")
+                .default_value("(standard input)"),
+        )
+        .arg(
+            Arg::with_name("null_data")
+                .short("z")
+                .long("null-data")
+                .help("Lines are terminated by NUL instead of newline"),
+        )
+        .arg(
+            Arg::with_name("null")
+                .short("Z")
+                .long("null")
+                .help("Print NUL after file names instead of \":\" or newline"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .value_name("WHEN")
+                .long("color")
+                .help("Highlight matches, file names and separators")
+                .possible_values(&["auto", "always", "never"])
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true),
+        )
+        .arg(
+            Arg::with_name("after")
+                .value_name("NUM")
+                .short("A")
+                .long("after-context")
+                .help("Print NUM lines of trailing context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("before")
+                .value_name("NUM")
+                .short("B")
+                .long("before-context")
+                .help("Print NUM lines of leading context")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("context")
+                .value_name("NUM")
+                .short("C")
+                .long("context")
+                .help("Print NUM lines of leading and trailing context")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("recursive")
                 .short("r")
                 .long("recursive")
                 .help("Recursive search"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("respect_gitignore")
+                .long("respect-gitignore")
+                .help("Skip what .gitignore files say to ignore"),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("S")
+                .long("follow")
+                .help("Follow symbolic links when searching recursively"),
+        )
+        .arg(
+            Arg::with_name("include")
+                .value_name("GLOB")
+                .long("include")
+                .help("Only search files whose name matches GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .value_name("GLOB")
+                .long("exclude")
+                .help("Skip files whose name matches GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("exclude_dir")
+                .value_name("GLOB")
+                .long("exclude-dir")
+                .help("Skip directories whose name matches GLOB")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .get_matches_safe()
+        .unwrap_or_else(|e| match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            // Usage errors are errors like any other, as far as the exit
+            // status goes
+            _ => {
+                eprintln!("{}", e.message);
+                process::exit(2);
+            }
+        });
 
+    let null_data = matches.is_present("null_data");
     let pattern = matches
         .value_of("pattern")
         .map(|p| {
-            RegexBuilder::new(p)
+            // A whole line may only be followed by its line ending, and a
+            // whole word has to sit between non-word characters or the ends
+            // of the line, checked without taking them up so that adjacent
+            // words both match. Only the part in the "grepr" group gets
+            // highlighted.
+            let source = if matches.is_present("line_regexp") {
+                let eol = if null_data { r"\x00" } else { r"\r?\n" };
+                format!(r"^(?P<grepr>{})(?:{})?$", p, eol)
+            } else if matches.is_present("word_regexp") {
+                format!(r"\b{{start-half}}(?P<grepr>{})\b{{end-half}}", p)
+            } else {
+                format!("(?P<grepr>{})", p)
+            };
+
+            RegexBuilder::new(&source)
                 .case_insensitive(matches.is_present("insensitive"))
                 .build()
                 .map_err(|_| format!("Invalid pattern \"{}\"", p))
@@ -72,55 +331,471 @@ pub fn get_args() -> MyResult<Config> {
         .unwrap();
     let files = matches.values_of_lossy("files").unwrap();
     let recursive = matches.is_present("recursive");
+    let walk = Walk {
+        include: parse_globs(matches.values_of_lossy("include"), "--include")?,
+        exclude: parse_globs(matches.values_of_lossy("exclude"), "--exclude")?,
+        exclude_dir: parse_globs(matches.values_of_lossy("exclude_dir"), "--exclude-dir")?,
+        follow_links: matches.is_present("follow"),
+        gitignore: matches.is_present("respect_gitignore"),
+    };
     let count = matches.is_present("count");
     let invert_match = matches.is_present("invert-match");
+    let files_with_matches = matches.is_present("files_with_matches");
+    let files_without_match = matches.is_present("files_without_match");
+
+    // The last of -H and -h wins, otherwise it depends on the number of files
+    let with_filename = if matches.is_present("with_filename") {
+        Some(true)
+    } else if matches.is_present("no_filename") {
+        Some(false)
+    } else {
+        None
+    };
+
+    // NUL bytes are just line endings with -z
+    let binary_files = match matches.value_of("binary_files") {
+        _ if matches.is_present("text") || null_data => BinaryFiles::Text,
+        Some("text") => BinaryFiles::Text,
+        Some("without-match") => BinaryFiles::WithoutMatch,
+        _ => BinaryFiles::Binary,
+    };
+
+    // A bare --color means auto, which only colors a terminal
+    let colors = match matches.value_of("color") {
+        Some("always") => true,
+        None if matches.is_present("color") => io::stdout().is_terminal(),
+        Some("auto") => io::stdout().is_terminal(),
+        _ => false,
+    };
+    let colors = if colors {
+        parse_colors(&env::var("GREP_COLORS").unwrap_or_default())
+    } else {
+        Colors::default()
+    };
+
+    // -A and -B take precedence over -C
+    let context = matches.value_of("context").map(parse_usize).transpose()?;
+    let before = matches
+        .value_of("before")
+        .map(parse_usize)
+        .transpose()?
+        .or(context)
+        .unwrap_or(0);
+    let after = matches
+        .value_of("after")
+        .map(parse_usize)
+        .transpose()?
+        .or(context)
+        .unwrap_or(0);
+
+    // A match found in the whole buffer points straight at the line it is
+    // in, as long as the pattern can't look past where it matched (no
+    // anchors or word boundaries) and only matching lines are wanted
+    let whole_buffer = !invert_match
+        && before == 0
+        && after == 0
+        && regex_syntax::parse(pattern.as_str())
+            .is_ok_and(|hir| hir.properties().look_set().is_empty());
 
     Ok(Config {
         pattern,
         files,
         recursive,
+        walk,
         count,
+        count_total: matches.is_present("count_total"),
+        replace: matches.value_of("replace").map(shift_groups),
         invert_match,
+        files_with_matches,
+        files_without_match,
+        with_filename,
+        before,
+        after,
+        quiet: matches.is_present("quiet"),
+        binary_files,
+        colors,
+        eol: if null_data { b'\0' } else { b'\n' },
+        null: matches.is_present("null"),
+        whole_buffer,
+        label: matches.value_of("label").unwrap().to_string(),
+        // Someone watching the terminal wants to see matches right away
+        line_buffered: matches.is_present("line_buffered") || io::stdout().is_terminal(),
+        no_messages: matches.is_present("no_messages"),
     })
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let entries = find_files(&config.files, config.recursive);
-    let num_files = entries.len();
+// Returns the exit status: 0 when some line was selected, 1 when none was,
+// and 2 when something went wrong
+pub fn run(config: Config) -> MyResult<i32> {
+    let entries = find_files(&config.files, config.recursive, &config.walk);
+
+    if config.quiet {
+        return Ok(quiet(entries, &config));
+    }
+
+    let show_filename = config.with_filename.unwrap_or(entries.len() > 1);
+
+    let context = config.before > 0 || config.after > 0;
+    let mut separate = false;
+    let mut selected = false;
+    let mut failed = false;
+    let mut total = 0;
+
+    // Line buffering lets each line through as soon as it's printed, at
+    // some cost when there is a lot of output
+    let stdout = io::stdout().lock();
+    let mut out: Box<dyn Write> = if config.line_buffered {
+        Box::new(LineWriter::new(stdout))
+    } else {
+        Box::new(BufWriter::new(stdout))
+    };
 
-    let print = |fname: &str, val: &str| {
-        if num_files > 1 {
-            print!("{}:{}", fname, val);
+    // Matching lines get a ":" after the file name, context lines a "-",
+    // unless -Z asks for a NUL
+    let colors = &config.colors;
+    let print = |out: &mut dyn Write, fname: &str, sep: &str, val: &str| {
+        if show_filename && config.null {
+            write!(out, "{}\0{}", paint(&colors.filename, fname), val)
+        } else if show_filename {
+            write!(
+                out,
+                "{}{}{}",
+                paint(&colors.filename, fname),
+                paint(&colors.separator, sep),
+                val
+            )
         } else {
-            print!("{}", val);
+            write!(out, "{}", val)
         }
     };
 
     for entry in entries {
-        match entry {
-            Err(e) => eprintln!("{}", e),
-            Ok(filename) => match open(&filename) {
-                Err(e) => eprintln!("{}: {}", filename, e),
-                Ok(file) => match find_lines(file, &config.pattern, config.invert_match) {
-                    Err(e) => eprintln!("{}", e),
-                    Ok(lines) => {
-                        if config.count {
-                            print(&filename, &format!("{}\n", lines.len()));
-                        } else {
-                            for line in &lines {
-                                print(&filename, line);
-                            }
-                        }
-                    }
-                },
-            },
+        let (filename, file, binary) =
+            match entry.and_then(|filename| match search_open(&filename, &config) {
+                Ok((file, binary)) => Ok((filename, file, binary)),
+                Err(e) => Err(format!("{}: {}", filename, e).into()),
+            }) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    report(&config, e);
+                    failed = true;
+                    continue;
+                }
+            };
+        let filename = match filename.as_str() {
+            "-" => config.label.clone(),
+            _ => filename,
+        };
+
+        let res = if config.files_with_matches || config.files_without_match {
+            input_has_match(file, &config).map(|found| {
+                if found == config.files_with_matches {
+                    let end = if config.null { '\0' } else { '\n' };
+                    selected = true;
+                    write!(out, "{}{}", paint(&colors.filename, &filename), end)?;
+                }
+                Ok(())
+            })
+        } else if config.count_total {
+            input_lines(file, &config).map(|lines| {
+                selected |= !lines.is_empty();
+                total += lines.len();
+                Ok(())
+            })
+        } else if config.count {
+            input_lines(file, &config).map(|lines| {
+                selected |= !lines.is_empty();
+                print(&mut out, &filename, ":", &format!("{}\n", lines.len()))
+            })
+        } else if binary {
+            // Matching lines from a binary file would only garble the output
+            input_has_match(file, &config).map(|found| {
+                if found {
+                    selected = true;
+                    writeln!(out, "Binary file {} matches", filename)?;
+                }
+                Ok(())
+            })
+        } else {
+            input_context(file, &config, |new_group, matched, line| {
+                selected = true;
+                // Like GNU grep, "--" goes between regions that aren't next
+                // to each other
+                if new_group && context && separate {
+                    writeln!(out, "{}", paint(&colors.separator, "--"))?;
+                }
+                separate = true;
+
+                if !matched {
+                    print(&mut out, &filename, "-", &line)
+                } else if config.invert_match {
+                    print(&mut out, &filename, ":", &line)
+                } else if let Some(template) = &config.replace {
+                    print(
+                        &mut out,
+                        &filename,
+                        ":",
+                        &replace(&line, &config.pattern, template),
+                    )
+                } else {
+                    print(
+                        &mut out,
+                        &filename,
+                        ":",
+                        &highlight(&line, &config.pattern, &colors.matched),
+                    )
+                }
+            })
+        };
+
+        // Failing to read a file only fails that file, but failing to
+        // write is the end of it
+        match res {
+            Ok(written) => written?,
+            Err(e) => {
+                report(&config, e);
+                failed = true;
+            }
         }
     }
 
-    Ok(())
+    // Files that couldn't be read count for nothing
+    if config.count_total {
+        writeln!(out, "{}", total)?;
+    }
+    out.flush()?;
+
+    Ok(if failed {
+        2
+    } else if selected {
+        0
+    } else {
+        1
+    })
+}
+
+// Nothing but errors gets printed, and the first match ends the search
+// with success, even after an error
+fn quiet(entries: Vec<MyResult<String>>, config: &Config) -> i32 {
+    let mut failed = false;
+
+    for entry in entries {
+        let res = entry.and_then(|filename| {
+            let (file, _) =
+                search_open(&filename, config).map_err(|e| format!("{}: {}", filename, e))?;
+            input_has_match(file, config)
+        });
+
+        match res {
+            Ok(true) => return 0,
+            Ok(false) => {}
+            Err(e) => {
+                report(config, e);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        2
+    } else {
+        1
+    }
+}
+
+// Also tells whether the file should only get a "Binary file matches"
+// summary; with --binary-files=without-match a binary file reads as empty
+fn search_open(filename: &str, config: &Config) -> MyResult<(Input, bool)> {
+    let mut input = match map(filename, config)? {
+        Some(mmap) => Input::Mapped(mmap),
+        None => Input::Stream(open(filename)?),
+    };
+    let binary = config.binary_files != BinaryFiles::Text
+        && match &mut input {
+            Input::Stream(file) => is_binary(file)?,
+            // Same sniff as when streaming, just the first buffer full
+            Input::Mapped(mmap) => is_binary(&mut BufReader::new(&mmap[..]))?,
+        };
+
+    if binary && config.binary_files == BinaryFiles::WithoutMatch {
+        return Ok((Input::Stream(Box::new(BufReader::new(io::empty()))), false));
+    }
+
+    Ok((input, binary))
+}
+
+// Only big regular files in valid UTF-8 are worth mapping, and only when
+// the whole buffer can be searched at once
+fn map(filename: &str, config: &Config) -> MyResult<Option<Mmap>> {
+    if !config.whole_buffer || filename == "-" {
+        return Ok(None);
+    }
+
+    let file = File::open(filename)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() || metadata.len() < MMAP_MIN_LEN {
+        return Ok(None);
+    }
+
+    // Safety: the map is only ever read, though a file truncated by someone
+    // else in the meantime can still bring the process down
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(std::str::from_utf8(&mmap).is_ok().then_some(mmap))
 }
 
-fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
+fn input_has_match(input: Input, config: &Config) -> MyResult<bool> {
+    match input {
+        Input::Stream(file) => has_match(file, &config.pattern, config.invert_match, config.eol),
+        Input::Mapped(mmap) => {
+            Ok(!find_mapped(&mmap, &config.pattern, config.eol, true).is_empty())
+        }
+    }
+}
+
+fn input_lines(input: Input, config: &Config) -> MyResult<Vec<String>> {
+    match input {
+        Input::Stream(file) => find_lines(file, &config.pattern, config.invert_match, config.eol),
+        Input::Mapped(mmap) => Ok(find_mapped(&mmap, &config.pattern, config.eol, false)
+            .into_iter()
+            .map(|range| String::from_utf8_lossy(&mmap[range]).into_owned())
+            .collect()),
+    }
+}
+
+// Mapped files never have context, so a group is just a run of matching
+// lines next to each other
+fn input_context(
+    input: Input,
+    config: &Config,
+    mut found: impl FnMut(bool, bool, String) -> io::Result<()>,
+) -> MyResult<io::Result<()>> {
+    match input {
+        Input::Stream(file) => find_context(
+            file,
+            &config.pattern,
+            config.invert_match,
+            config.eol,
+            config.before,
+            config.after,
+            found,
+        ),
+        Input::Mapped(mmap) => {
+            let mut last = None;
+            for range in find_mapped(&mmap, &config.pattern, config.eol, false) {
+                let line = String::from_utf8_lossy(&mmap[range.clone()]).into_owned();
+                if let Err(e) = found(last != Some(range.start), true, line) {
+                    return Ok(Err(e));
+                }
+                last = Some(range.end);
+            }
+            Ok(Ok(()))
+        }
+    }
+}
+
+// Starts from GNU grep's defaults, and only the capabilities grepr has any
+// use for are picked out of GREP_COLORS
+fn parse_colors(spec: &str) -> Colors {
+    let mut colors = Colors {
+        matched: "01;31".to_string(),
+        filename: "35".to_string(),
+        separator: "36".to_string(),
+    };
+
+    for cap in spec.split(':') {
+        match cap.split_once('=') {
+            Some(("mt" | "ms", sgr)) => colors.matched = sgr.to_string(),
+            Some(("fn", sgr)) => colors.filename = sgr.to_string(),
+            Some(("se", sgr)) => colors.separator = sgr.to_string(),
+            _ => {}
+        }
+    }
+
+    colors
+}
+
+fn paint(sgr: &str, text: &str) -> String {
+    if sgr.is_empty() {
+        text.to_string()
+    } else {
+        format!("\x1b[{}m\x1b[K{}\x1b[m\x1b[K", sgr, text)
+    }
+}
+
+fn highlight(line: &str, pattern: &Regex, sgr: &str) -> String {
+    if sgr.is_empty() {
+        return line.to_string();
+    }
+
+    let mut painted = String::new();
+    let mut last = 0;
+    for caps in pattern.captures_iter(line) {
+        let m = caps.name("grepr").unwrap_or_else(|| caps.get(0).unwrap());
+        if m.start() == m.end() {
+            continue;
+        }
+        painted.push_str(&line[last..m.start()]);
+        painted.push_str(&paint(sgr, m.as_str()));
+        last = m.end();
+    }
+    painted.push_str(&line[last..]);
+
+    painted
+}
+
+// Only the part in the "grepr" group is replaced, the way it gets
+// highlighted
+fn replace(line: &str, pattern: &Regex, template: &str) -> String {
+    let mut replaced = String::new();
+    let mut last = 0;
+    for caps in pattern.captures_iter(line) {
+        let m = caps.name("grepr").unwrap_or_else(|| caps.get(0).unwrap());
+        replaced.push_str(&line[last..m.start()]);
+        caps.expand(template, &mut replaced);
+        last = m.end();
+    }
+    replaced.push_str(&line[last..]);
+
+    replaced
+}
+
+// The whole pattern sits in the "grepr" group, which pushes the groups of
+// the pattern itself one number up, and makes "grepr" what $0 stands for
+fn shift_groups(template: &str) -> String {
+    let reference = Regex::new(r"\$(\$|\{([^}]*)\}|([_0-9a-zA-Z]+))").unwrap();
+    reference
+        .replace_all(template, |caps: &Captures| {
+            let name = caps.get(2).or(caps.get(3)).map_or("", |m| m.as_str());
+            match name.parse::<usize>() {
+                Ok(num) => format!("${{{}}}", num + 1),
+                Err(_) => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+fn parse_globs(globs: Option<Vec<String>>, flag: &str) -> MyResult<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs.unwrap_or_default() {
+        builder.add(Glob::new(&glob).map_err(|_| format!("Invalid {} \"{}\"", flag, glob))?);
+    }
+    Ok(builder.build()?)
+}
+
+// With -s a file that can't be read still counts for the exit status, it
+// just goes unmentioned
+fn report(config: &Config, e: Box<dyn Error>) {
+    if !config.no_messages {
+        eprintln!("{}", e);
+    }
+}
+
+fn parse_usize(val: &str) -> MyResult<usize> {
+    val.parse()
+        .map_err(|_| format!("\"{}\" not a valid integer", val).into())
+}
+
+fn find_files(paths: &[String], recursive: bool, walk: &Walk) -> Vec<MyResult<String>> {
     let mut files = vec![];
 
     for path in paths {
@@ -132,12 +807,53 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
                         if !recursive {
                             files.push(Err(From::from(format!("{} is a directory", path))));
                         } else {
+                            // The .gitignore files match against full
+                            // paths, as those from above the path have
+                            // nothing else in common with its entries
+                            let full =
+                                fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+                            let mut ignores = if walk.gitignore {
+                                parent_ignores(&full)
+                                    .into_iter()
+                                    .map(|gitignore| (None, gitignore))
+                                    .collect()
+                            } else {
+                                vec![]
+                            };
                             for entry in WalkDir::new(path)
+                                .follow_links(walk.follow_links)
                                 .into_iter()
-                                .flatten()
-                                .filter(|e| e.file_type().is_file())
+                                // Excluded and ignored directories aren't
+                                // even descended into, but the path given
+                                // is always searched
+                                .filter_entry(|e| {
+                                    let ignored = walk.gitignore
+                                        && gitignored(&mut ignores, e, Path::new(path), &full);
+                                    e.depth() == 0
+                                        || !ignored
+                                            && (!e.file_type().is_dir()
+                                                || !walk.exclude_dir.is_match(e.file_name()))
+                                })
                             {
-                                files.push(Ok(entry.path().display().to_string()));
+                                match entry {
+                                    Ok(e)
+                                        if e.file_type().is_file()
+                                            && (walk.include.is_empty()
+                                                || walk.include.is_match(e.file_name()))
+                                            && !walk.exclude.is_match(e.file_name()) =>
+                                    {
+                                        files.push(Ok(e.path().display().to_string()))
+                                    }
+                                    // A link back up the tree is skipped
+                                    // instead of followed around forever
+                                    Err(e) if e.loop_ancestor().is_some() => {
+                                        files.push(Err(From::from(format!(
+                                            "{}: recursive directory loop",
+                                            e.path().unwrap().display()
+                                        ))))
+                                    }
+                                    _ => {}
+                                }
                             }
                         }
                     } else if metadata.is_file() {
@@ -152,16 +868,75 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
     files
 }
 
+// Keeps the .gitignore files of the directories on the way down to `entry`
+// in `ignores`, along with their depth, or None for those from above the
+// path searched, `root`, and tells whether the deepest one with something to
+// say about `entry` ignores it
+fn gitignored(
+    ignores: &mut Vec<(Option<usize>, Gitignore)>,
+    entry: &DirEntry,
+    root: &Path,
+    full: &Path,
+) -> bool {
+    ignores.retain(|(depth, _)| *depth < Some(entry.depth()));
+    let full_path = match entry.path().strip_prefix(root) {
+        Ok(rest) if entry.depth() > 0 => full.join(rest),
+        _ => full.to_path_buf(),
+    };
+
+    let is_dir = entry.file_type().is_dir();
+    let ignored = (is_dir && entry.file_name() == ".git")
+        || ignores
+            .iter()
+            .rev()
+            .map(|(_, gitignore)| gitignore.matched(&full_path, is_dir))
+            .find(|matched| !matched.is_none())
+            .is_some_and(|matched| matched.is_ignore());
+
+    // The path searched has its own rules read even when ignored itself
+    if is_dir && (!ignored || entry.depth() == 0) {
+        let (gitignore, _) = Gitignore::new(full_path.join(".gitignore"));
+        if !gitignore.is_empty() {
+            ignores.push((Some(entry.depth()), gitignore));
+        }
+    }
+
+    ignored
+}
+
+// The ignore files of the repository `path` is in, if any, that the walk
+// won't come across: .git/info/exclude and the .gitignore files above it
+fn parent_ignores(path: &Path) -> Vec<Gitignore> {
+    let Some(top) = path.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return vec![];
+    };
+
+    let mut exclude = GitignoreBuilder::new(top);
+    exclude.add(top.join(".git/info/exclude"));
+    let mut ignores: Vec<_> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(top))
+        .map(|dir| Gitignore::new(dir.join(".gitignore")).0)
+        .chain(exclude.build().ok())
+        .filter(|gitignore| !gitignore.is_empty())
+        .collect();
+    ignores.reverse();
+
+    ignores
+}
+
 fn find_lines<T: BufRead>(
     mut file: T,
     pattern: &Regex,
     invert_match: bool,
+    eol: u8,
 ) -> MyResult<Vec<String>> {
     let mut line = String::new();
     let mut lines = vec![];
 
     loop {
-        let bytes = file.read_line(&mut line)?;
+        let bytes = read_line(&mut file, eol, &mut line)?;
 
         if bytes == 0 {
             break;
@@ -177,6 +952,140 @@ fn find_lines<T: BufRead>(
     Ok(lines)
 }
 
+// Hands each line worth printing to `found` as soon as it is known, along
+// with whether it starts a new group and whether it matched. A group has up
+// to `before` lines of context ahead of its matching lines and `after` lines
+// following them, and groups that touch or overlap are merged. What `found`
+// fails with gets handed back as is.
+fn find_context<T: BufRead>(
+    mut file: T,
+    pattern: &Regex,
+    invert_match: bool,
+    eol: u8,
+    before: usize,
+    after: usize,
+    mut found: impl FnMut(bool, bool, String) -> io::Result<()>,
+) -> MyResult<io::Result<()>> {
+    let mut line = String::new();
+    let mut previous = VecDeque::new();
+    let mut lines = vec![];
+    let mut pending = 0;
+    let mut last = None;
+
+    for num in 0.. {
+        let bytes = read_line(&mut file, eol, &mut line)?;
+
+        if bytes == 0 {
+            break;
+        }
+
+        let mut new_group = false;
+        if invert_match ^ pattern.is_match(line.as_str()) {
+            let start = num - previous.len();
+            new_group = last.is_none_or(|last| last + 1 < start);
+            lines.extend(previous.drain(..).map(|line| (false, line)));
+            lines.push((true, line.clone()));
+            pending = after;
+            last = Some(num);
+        } else if pending > 0 {
+            lines.push((false, line.clone()));
+            pending -= 1;
+            last = Some(num);
+        } else if before > 0 {
+            if previous.len() == before {
+                previous.pop_front();
+            }
+            previous.push_back(line.clone());
+        }
+
+        for (i, (matched, line)) in lines.drain(..).enumerate() {
+            if let Err(e) = found(new_group && i == 0, matched, line) {
+                return Ok(Err(e));
+            }
+        }
+
+        line.clear();
+    }
+
+    Ok(Ok(()))
+}
+
+// Runs the pattern over the whole buffer, which must be valid UTF-8, and
+// returns the byte ranges of the lines the matches fall in. A match may run
+// past the end of its line, so every line found still gets matched on its
+// own, like when reading line by line, before the search goes on after it.
+fn find_mapped(buf: &[u8], pattern: &Regex, eol: u8, first_only: bool) -> Vec<Range<usize>> {
+    let text = std::str::from_utf8(buf).unwrap_or_default();
+    let mut lines = vec![];
+    let mut pos = 0;
+
+    while let Some(m) = pattern.find_at(text, pos) {
+        let start = buf[..m.start()]
+            .iter()
+            .rposition(|&b| b == eol)
+            .map_or(0, |i| i + 1);
+        if start == buf.len() {
+            break;
+        }
+        let end = buf[m.start()..]
+            .iter()
+            .position(|&b| b == eol)
+            .map_or(buf.len(), |i| m.start() + i + 1);
+
+        if pattern.is_match(&text[start..end]) {
+            lines.push(start..end);
+            if first_only {
+                break;
+            }
+        }
+
+        if end == buf.len() {
+            break;
+        }
+        pos = end;
+    }
+
+    lines
+}
+
+// Like BufRead::read_line, but lines end with `eol` and invalid UTF-8 gets
+// replaced instead of failing the whole file
+fn read_line<T: BufRead>(file: &mut T, eol: u8, line: &mut String) -> io::Result<usize> {
+    let mut buf = vec![];
+    let bytes = file.read_until(eol, &mut buf)?;
+    line.push_str(&String::from_utf8_lossy(&buf));
+    Ok(bytes)
+}
+
+// Like GNU grep, a NUL byte early on gives a binary file away
+fn is_binary<T: BufRead>(file: &mut T) -> io::Result<bool> {
+    Ok(file.fill_buf()?.contains(&0))
+}
+
+// Stops reading at the first matching line
+fn has_match<T: BufRead>(
+    mut file: T,
+    pattern: &Regex,
+    invert_match: bool,
+    eol: u8,
+) -> MyResult<bool> {
+    let mut line = String::new();
+
+    loop {
+        let bytes = read_line(&mut file, eol, &mut line)?;
+
+        if bytes == 0 {
+            return Ok(false);
+        }
+
+        if invert_match ^ pattern.is_match(line.as_str()) {
+            return Ok(true);
+        }
+
+        line.clear();
+    }
+}
+
 fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     match filename {
         "-" => Ok(Box::new(BufReader::new(io::stdin()))),
@@ -186,27 +1095,43 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
 
 #[cfg(test)]
 mod tests {
-    use super::{find_files, find_lines};
+    use super::{
+        find_context, find_files, find_lines, find_mapped, has_match, highlight, is_binary,
+        parse_colors, replace, shift_groups, Colors, Walk,
+    };
+    use globset::{Glob, GlobSet, GlobSetBuilder};
     use rand::{distributions::Alphanumeric, Rng};
     use regex::{Regex, RegexBuilder};
     use std::io::Cursor;
 
+    fn globs(globs: &[&str]) -> GlobSet {
+        let mut builder = GlobSetBuilder::new();
+        for glob in globs {
+            builder.add(Glob::new(glob).unwrap());
+        }
+        builder.build().unwrap()
+    }
+
     #[test]
     fn test_find_files() {
         // Verify that the function finds a file known to exist
-        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
+        let files = find_files(
+            &["./tests/inputs/fox.txt".to_string()],
+            false,
+            &Walk::default(),
+        );
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
 
         // The function should reject a directory without the recursive option
-        let files = find_files(&["./tests/inputs".to_string()], false);
+        let files = find_files(&["./tests/inputs".to_string()], false, &Walk::default());
         assert_eq!(files.len(), 1);
         if let Err(e) = &files[0] {
             assert_eq!(e.to_string(), "./tests/inputs is a directory");
         }
 
         // Verify the function recurses to find four files in the directory
-        let res = find_files(&["./tests/inputs".to_string()], true);
+        let res = find_files(&["./tests/inputs".to_string()], true, &Walk::default());
         let mut files: Vec<String> = res
             .iter()
             .map(|r| r.as_ref().unwrap().replace("\\", "/"))
@@ -223,6 +1148,24 @@ mod tests {
             ]
         );
 
+        // Globs pick the files, and excluded directories are skipped whole
+        let walk = Walk {
+            include: globs(&["*.txt"]),
+            exclude: globs(&["[bn]*"]),
+            exclude_dir: globs(&["expected"]),
+            ..Default::default()
+        };
+        let res = find_files(&["./tests".to_string()], true, &walk);
+        let mut files: Vec<String> = res
+            .iter()
+            .map(|r| r.as_ref().unwrap().replace("\\", "/"))
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec!["./tests/inputs/empty.txt", "./tests/inputs/fox.txt"]
+        );
+
         // Generate a random string to represent a nonexistent file
         let bad: String = rand::thread_rng()
             .sample_iter(&Alphanumeric)
@@ -231,7 +1174,7 @@ mod tests {
             .collect();
 
         // Verify that the function returns the bad file as an error
-        let files = find_files(&[bad], false);
+        let files = find_files(&[bad], false, &Walk::default());
         assert_eq!(files.len(), 1);
         assert!(files[0].is_err());
     }
@@ -242,12 +1185,12 @@ mod tests {
 
         // The pattern _or_ should match the one line, "Lorem"
         let re1 = Regex::new("or").unwrap();
-        let matches = find_lines(Cursor::new(&text), &re1, false);
+        let matches = find_lines(Cursor::new(&text), &re1, false, b'\n');
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 1);
 
         // When inverted, the function should match the other two lines
-        let matches = find_lines(Cursor::new(&text), &re1, true);
+        let matches = find_lines(Cursor::new(&text), &re1, true, b'\n');
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 2);
 
@@ -258,13 +1201,209 @@ mod tests {
             .unwrap();
 
         // The two lines "Lorem" and "DOLOR" should match
-        let matches = find_lines(Cursor::new(&text), &re2, false);
+        let matches = find_lines(Cursor::new(&text), &re2, false, b'\n');
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 2);
 
         // When inverted, the one remaining line should match
-        let matches = find_lines(Cursor::new(&text), &re2, true);
+        let matches = find_lines(Cursor::new(&text), &re2, true, b'\n');
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 1);
+
+        // With NUL-terminated lines, newlines are just part of the line
+        let text = b"Lorem\nIpsum\0DOLOR";
+        let matches = find_lines(Cursor::new(&text), &re1, false, b'\0');
+        assert_eq!(matches.unwrap(), vec!["Lorem\nIpsum\0"]);
+    }
+
+    #[test]
+    fn test_has_match() {
+        let text = b"Lorem\nIpsum\r\nDOLOR";
+
+        let re = Regex::new("or").unwrap();
+        assert!(has_match(Cursor::new(&text), &re, false, b'\n').unwrap());
+        assert!(has_match(Cursor::new(&text), &re, true, b'\n').unwrap());
+
+        // Every line matches, so none is left when inverted
+        let re = Regex::new("[a-zA-Z]").unwrap();
+        assert!(has_match(Cursor::new(&text), &re, false, b'\n').unwrap());
+        assert!(!has_match(Cursor::new(&text), &re, true, b'\n').unwrap());
+
+        // Nothing to match in an empty file
+        assert!(!has_match(Cursor::new(b""), &re, false, b'\n').unwrap());
+
+        // Invalid UTF-8 gets replaced rather than failing the search
+        let text = b"Lorem\n\xff\n";
+        assert!(has_match(Cursor::new(&text), &Regex::new("or").unwrap(), false, b'\n').unwrap());
+        assert!(!has_match(Cursor::new(&text), &Regex::new("x").unwrap(), false, b'\n').unwrap());
+        assert!(has_match(
+            Cursor::new(&text),
+            &Regex::new("\u{FFFD}").unwrap(),
+            false,
+            b'\n'
+        )
+        .unwrap());
+    }
+
+    fn context(
+        text: &[u8],
+        re: &Regex,
+        invert_match: bool,
+        before: usize,
+        after: usize,
+    ) -> Vec<Vec<(bool, String)>> {
+        let mut groups: Vec<Vec<(bool, String)>> = vec![];
+        find_context(
+            text,
+            re,
+            invert_match,
+            b'\n',
+            before,
+            after,
+            |new_group, matched, line| {
+                if new_group {
+                    groups.push(vec![]);
+                }
+                groups.last_mut().unwrap().push((matched, line));
+                Ok(())
+            },
+        )
+        .unwrap()
+        .unwrap();
+        groups
+    }
+
+    #[test]
+    fn test_find_context() {
+        let text = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let re = Regex::new("two|six").unwrap();
+        let lines = |group: &[(bool, String)]| {
+            group
+                .iter()
+                .map(|(matched, line)| format!("{}{}", if *matched { ':' } else { '-' }, line))
+                .collect::<String>()
+        };
+
+        // Without context every match is a group of its own
+        let groups = context(text, &re, false, 0, 0);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(lines(&groups[0]), ":two\n");
+        assert_eq!(lines(&groups[1]), ":six\n");
+
+        // Leading context stops at the start of the file
+        let groups = context(text, &re, false, 2, 0);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(lines(&groups[0]), "-one\n:two\n");
+        assert_eq!(lines(&groups[1]), "-four\n-five\n:six\n");
+
+        // Trailing context stops at the end of the file
+        let groups = context(text, &re, false, 0, 2);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(lines(&groups[0]), ":two\n-three\n-four\n");
+        assert_eq!(lines(&groups[1]), ":six\n-seven\n");
+
+        // Adjacent regions are merged
+        let groups = context(text, &re, false, 1, 2);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            lines(&groups[0]),
+            "-one\n:two\n-three\n-four\n-five\n:six\n-seven\n"
+        );
+
+        // Context around inverted matches
+        let re = Regex::new("o").unwrap();
+        let groups = context(text, &re, true, 1, 1);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            lines(&groups[0]),
+            "-two\n:three\n-four\n:five\n:six\n:seven\n"
+        );
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(&mut Cursor::new(b"Lorem\nIpsum\n")).unwrap());
+        assert!(is_binary(&mut Cursor::new(b"Lorem\n\0Ipsum\n")).unwrap());
+        assert!(!is_binary(&mut Cursor::new(b"")).unwrap());
+    }
+
+    #[test]
+    fn test_parse_colors() {
+        let defaults = Colors {
+            matched: "01;31".to_string(),
+            filename: "35".to_string(),
+            separator: "36".to_string(),
+        };
+        assert_eq!(parse_colors(""), defaults);
+        assert_eq!(parse_colors("foo:sl=1"), defaults);
+
+        let colors = parse_colors("ms=04;32:fn=:ln=33");
+        assert_eq!(colors.matched, "04;32");
+        assert_eq!(colors.filename, "");
+        assert_eq!(colors.separator, "36");
+    }
+
+    #[test]
+    fn test_highlight() {
+        let re = Regex::new("(?P<grepr>o+)").unwrap();
+        assert_eq!(
+            highlight("foo bar boo\n", &re, "31"),
+            "f\x1b[31m\x1b[Koo\x1b[m\x1b[K bar b\x1b[31m\x1b[Koo\x1b[m\x1b[K\n"
+        );
+        assert_eq!(highlight("foo\n", &re, ""), "foo\n");
+
+        // Only the word itself, not what surrounds it, and every one of
+        // them
+        let re = Regex::new(r"\b{start-half}(?P<grepr>bar)\b{end-half}").unwrap();
+        assert_eq!(
+            highlight("foo bar.\n", &re, "31"),
+            "foo \x1b[31m\x1b[Kbar\x1b[m\x1b[K.\n"
+        );
+        assert_eq!(
+            highlight("bar bar\n", &re, "31"),
+            "\x1b[31m\x1b[Kbar\x1b[m\x1b[K \x1b[31m\x1b[Kbar\x1b[m\x1b[K\n"
+        );
+    }
+
+    #[test]
+    fn test_find_mapped() {
+        let text = b"Lorem\nIpsum\r\nDOLOR\nsit amet";
+        let re = Regex::new("or").unwrap();
+        assert_eq!(find_mapped(text, &re, b'\n', false), vec![0..6]);
+        assert_eq!(find_mapped(text, &re, b'\n', true), vec![0..6]);
+
+        // The last line need not end with a newline
+        let re = Regex::new("(?i)or|et").unwrap();
+        assert_eq!(
+            find_mapped(text, &re, b'\n', false),
+            vec![0..6, 13..19, 19..27]
+        );
+        assert_eq!(find_mapped(text, &re, b'\n', true), vec![0..6]);
+
+        // A match running into the next line doesn't make either one match,
+        // nor hide a match further on
+        let re = Regex::new(r"m\s+D|DOLOR").unwrap();
+        assert_eq!(find_mapped(text, &re, b'\n', false), vec![13..19]);
+
+        assert!(find_mapped(b"", &re, b'\n', false).is_empty());
+        assert!(find_mapped(text, &Regex::new("x").unwrap(), b'\n', false).is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(
+            shift_groups("$0-$1 ${2}x $name ${name} $$1"),
+            "${1}-${2} ${3}x $name ${name} $$1"
+        );
+
+        let re = Regex::new(r"(?P<grepr>(\w+)@(?P<host>\w+))").unwrap();
+        let template = shift_groups("$1 at ${host} ($0)");
+        assert_eq!(
+            replace("mail foo@bar or baz@qux\n", &re, &template),
+            "mail foo at bar (foo@bar) or baz at qux (baz@qux)\n"
+        );
+
+        // The line stays as it was without a match
+        assert_eq!(replace("foo\n", &re, &template), "foo\n");
     }
 }
@@ -1,7 +1,10 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
-use std::{fs, path::Path};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{fs, path::Path, process, thread};
 use sys_info::os_type;
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
@@ -42,7 +45,7 @@ fn dies_no_args() -> TestResult {
 #[test]
 fn dies_bad_pattern() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["*foo", FOX])
+        .args(["*foo", FOX])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Invalid pattern \"*foo\""));
@@ -55,7 +58,7 @@ fn warns_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&["foo", &bad])
+        .args(["foo", &bad])
         .assert()
         .stderr(predicate::str::is_match(expected)?);
     Ok(())
@@ -64,15 +67,13 @@ fn warns_bad_file() -> TestResult {
 // --------------------------------------------------
 fn run(args: &[&str], expected_file: &str) -> TestResult {
     let windows_file = format!("{}.windows", expected_file);
-    let expected_file = if os_type().unwrap() == "Windows"
-        && Path::new(&windows_file).is_file()
-    {
+    let expected_file = if os_type().unwrap() == "Windows" && Path::new(&windows_file).is_file() {
         &windows_file
     } else {
         expected_file
     };
 
-    let expected = fs::read_to_string(&expected_file)?;
+    let expected = fs::read_to_string(expected_file)?;
 
     Command::cargo_bin(PRG)?
         .args(args)
@@ -234,7 +235,7 @@ fn warns_dir_not_recursive() -> TestResult {
     let stdout = "tests/inputs/fox.txt:\
         The quick brown fox jumps over the lazy dog.";
     Command::cargo_bin(PRG)?
-        .args(&["fox", INPUTS_DIR, FOX])
+        .args(["fox", INPUTS_DIR, FOX])
         .assert()
         .stderr(predicate::str::contains("tests/inputs is a directory"))
         .stdout(predicate::str::contains(stdout));
@@ -245,8 +246,7 @@ fn warns_dir_not_recursive() -> TestResult {
 #[test]
 fn stdin() -> TestResult {
     let input = fs::read_to_string(BUSTLE)?;
-    let expected =
-        fs::read_to_string("tests/expected/bustle.txt.the.capitalized")?;
+    let expected = fs::read_to_string("tests/expected/bustle.txt.the.capitalized")?;
 
     Command::cargo_bin(PRG)?
         .arg("The")
@@ -266,14 +266,777 @@ fn stdin_insensitive_count() -> TestResult {
         input += &fs::read_to_string(file)?;
     }
 
-    let expected_file =
-        "tests/expected/the.recursive.insensitive.count.stdin";
+    let expected_file = "tests/expected/the.recursive.insensitive.count.stdin";
     let expected = fs::read_to_string(expected_file)?;
 
     Command::cargo_bin(PRG)?
-        .args(&["-ci", "the", "-"])
+        .args(["-ci", "the", "-"])
         .write_stdin(input)
         .assert()
         .stdout(expected);
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "The", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}\n{}\n{}\n", BUSTLE, FOX, NOBODY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches_count() -> TestResult {
+    // Listing files takes precedence over counting
+    Command::cargo_bin(PRG)?
+        .args(["-lc", "dog", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-L", "The", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}\n", EMPTY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match_inverted() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-Lv", "fox", FOX, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!("{}\n{}\n", FOX, EMPTY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_files_with_and_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "-L", "The", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn with_filename() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-H", "dog", FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:The quick brown fox jumps over the lazy dog.\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn with_filename_count_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-Hc", "dog"])
+        .write_stdin(fs::read_to_string(FOX)?)
+        .assert()
+        .success()
+        .stdout("(standard input):1\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_filename() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-h", "dog", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn filename_last_flag_wins() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-h", "-H", "dog", FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:The quick brown fox jumps over the lazy dog.\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp() -> TestResult {
+    // "the" inside "Then" or "they'd" is not a word of its own
+    Command::cargo_bin(PRG)?
+        .args(["-w", "the", BUSTLE, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}:The sweeping up the heart,\n\
+             {}:To tell one's name—the livelong June—\r\n",
+            BUSTLE, NOBODY
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp_insensitive() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-wi", "the", BUSTLE])
+        .assert()
+        .success()
+        .stdout(
+            "The bustle in a house\n\
+             The morning after death\n\
+             The sweeping up the heart,\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp_inverted() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-wvc", "tel", NOBODY])
+        .assert()
+        .success()
+        .stdout("9\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-x", "Until eternity.|Until", BUSTLE])
+        .assert()
+        .success()
+        .stdout("Until eternity.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp_crlf() -> TestResult {
+    // The line ending is not part of the line
+    Command::cargo_bin(PRG)?
+        .args(["-xi", "then there's a pair of us!", NOBODY])
+        .assert()
+        .success()
+        .stdout("Then there's a pair of us!\r\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp_empty() -> TestResult {
+    // Only the blank lines match an empty pattern
+    Command::cargo_bin(PRG)?
+        .args(["-xc", "", BUSTLE, NOBODY])
+        .assert()
+        .success()
+        .stdout(format!("{}:1\n{}:1\n", BUSTLE, NOBODY));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp_inverted() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-xvc", "Until", BUSTLE])
+        .assert()
+        .success()
+        .stdout("9\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn after_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-A1", "The", BUSTLE])
+        .assert()
+        .success()
+        .stdout(
+            "The bustle in a house\n\
+             The morning after death\n\
+             Is solemnest of industries\n\
+             --\n\
+             The sweeping up the heart,\n\
+             And putting love away\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn before_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-B", "1", "e.ernity", BUSTLE])
+        .assert()
+        .success()
+        .stdout("We shall not want to use again\nUntil eternity.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context_multiple_files() -> TestResult {
+    // Context lines get a "-" after the file name, and files are
+    // separated like any other region
+    Command::cargo_bin(PRG)?
+        .args(["-C1", "the", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{0}-\n\
+             {0}:The sweeping up the heart,\n\
+             {0}-And putting love away\n\
+             --\n\
+             {1}:The quick brown fox jumps over the lazy dog.\n",
+            BUSTLE, FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn context_overridden() -> TestResult {
+    // -A wins over -C
+    Command::cargo_bin(PRG)?
+        .args(["-C2", "-A0", "Until", BUSTLE])
+        .assert()
+        .success()
+        .stdout(
+            "And putting love away\n\
+             We shall not want to use again\n\
+             Until eternity.\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_context() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-C", "foo", "the", BUSTLE])
+        .assert()
+        .failure()
+        .stderr("\"foo\" not a valid integer\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "dog", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_no_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "cat", BUSTLE, FOX])
+        .assert()
+        .failure()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_stops_at_first_match() -> TestResult {
+    // The missing file is never reached
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-q", "dog", FOX, &bad])
+        .assert()
+        .success()
+        .stdout("")
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn status_match() -> TestResult {
+    Command::cargo_bin(PRG)?.args(["dog", FOX]).assert().code(0);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn status_no_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "cat", FOX])
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn status_error() -> TestResult {
+    // A match doesn't make up for a missing file
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["dog", FOX, &bad])
+        .assert()
+        .code(2)
+        .stdout(format!(
+            "{}:The quick brown fox jumps over the lazy dog.\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn status_quiet_error() -> TestResult {
+    // Unless the match is all -q is waiting for
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-q", "dog", &bad, FOX])
+        .assert()
+        .code(0);
+
+    Command::cargo_bin(PRG)?
+        .args(["-q", "cat", &bad, FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn status_usage_error() -> TestResult {
+    Command::cargo_bin(PRG)?.assert().code(2);
+    Command::cargo_bin(PRG)?
+        .args(["*foo", FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["fox", "-"])
+        .write_stdin("The quick\0brown fox\n")
+        .assert()
+        .code(0)
+        .stdout("Binary file (standard input) matches\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["dog", "-"])
+        .write_stdin("The quick\0brown fox\n")
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file_text() -> TestResult {
+    for flag in ["-a", "--binary-files=text"] {
+        Command::cargo_bin(PRG)?
+            .args([flag, "fox", "-"])
+            .write_stdin("The quick\0brown fox\n")
+            .assert()
+            .code(0)
+            .stdout("The quick\0brown fox\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_file_without_match() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files=without-match", "-c", "fox", "-"])
+        .write_stdin("The quick\0brown fox\n")
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_binary_files() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files=foo", "fox", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("isn't a valid value"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn recursive_globs() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args([
+            "-r",
+            "--include",
+            "*.txt",
+            "--exclude-dir",
+            "expected",
+            "-i",
+            "the",
+            "tests",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "tests/inputs/fox.txt:The quick brown fox",
+        ))
+        .stdout(predicate::str::contains("tests/expected").not());
+
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--exclude", "*.txt", "dog", INPUTS_DIR])
+        .assert()
+        .code(1)
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_glob() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-r", "--exclude-dir", "[a", "dog", INPUTS_DIR])
+        .assert()
+        .code(2)
+        .stderr("Invalid --exclude-dir \"[a\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_always() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "-H", "dog", FOX])
+        .env_remove("GREP_COLORS")
+        .assert()
+        .success()
+        .stdout(
+            "\x1b[35m\x1b[Ktests/inputs/fox.txt\x1b[m\x1b[K\
+            \x1b[36m\x1b[K:\x1b[m\x1b[K\
+            The quick brown fox jumps over the lazy \x1b[01;31m\x1b[Kdog\x1b[m\x1b[K.\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_grep_colors() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "-H", "dog", FOX])
+        .env("GREP_COLORS", "ms=04:fn=:se=")
+        .assert()
+        .success()
+        .stdout(
+            "tests/inputs/fox.txt:\
+            The quick brown fox jumps over the lazy \x1b[04m\x1b[Kdog\x1b[m\x1b[K.\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_not_a_tty() -> TestResult {
+    for flag in ["--color", "--color=auto", "--color=never"] {
+        Command::cargo_bin(PRG)?
+            .args([flag, "dog", FOX])
+            .assert()
+            .success()
+            .stdout("The quick brown fox jumps over the lazy dog.\n");
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-z", "dog", "-"])
+        .write_stdin("The quick\nbrown fox\0jumps over\nthe lazy dog\0")
+        .assert()
+        .success()
+        .stdout("jumps over\nthe lazy dog\0");
+
+    Command::cargo_bin(PRG)?
+        .args(["-zx", "brown fox", "-"])
+        .write_stdin("The quick\0brown fox\0")
+        .assert()
+        .success()
+        .stdout("brown fox\0");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_filenames() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-Z", "-l", "dog", FOX, EMPTY])
+        .assert()
+        .success()
+        .stdout("tests/inputs/fox.txt\0");
+
+    Command::cargo_bin(PRG)?
+        .args(["-ZH", "dog", FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/fox.txt\0The quick brown fox jumps over the lazy dog.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_label() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["dog", "-", FOX])
+        .write_stdin("The lazy dog\n")
+        .assert()
+        .success()
+        .stdout(
+            "(standard input):The lazy dog\n\
+            tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n",
+        );
+
+    Command::cargo_bin(PRG)?
+        .args(["-l", "--label=foo.gz", "dog", "-", EMPTY])
+        .write_stdin("The lazy dog\n")
+        .assert()
+        .success()
+        .stdout("foo.gz\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_links() -> TestResult {
+    // Links aren't followed by default
+    Command::cargo_bin(PRG)?
+        .args(["-r", "dog", "tests/links"])
+        .assert()
+        .code(1)
+        .stdout("");
+
+    // The link back to the same directory is reported, not followed forever
+    Command::cargo_bin(PRG)?
+        .args(["-rS", "dog", "tests/links"])
+        .assert()
+        .code(2)
+        .stdout("tests/links/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n")
+        .stderr("tests/links/loop: recursive directory loop\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn line_buffered() -> TestResult {
+    // The match has to come out while stdin is still open
+    let mut child = process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(["--line-buffered", "dog", "-"])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "The lazy dog")?;
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        tx.send(stdout.read_line(&mut line).map(|_| line)).unwrap();
+    });
+    let line = rx.recv_timeout(Duration::from_secs(10));
+
+    drop(stdin);
+    child.wait()?;
+    assert_eq!(line??, "The lazy dog\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn no_messages() -> TestResult {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-s", "dog", &bad, INPUTS_DIR, FOX])
+        .assert()
+        .code(2)
+        .stderr("")
+        .stdout("tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-qs", "cat", &bad])
+        .assert()
+        .code(2)
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_gitignore() -> TestResult {
+    let dir = std::env::temp_dir().join(format!("grepr-{}", gen_bad_file()));
+    fs::create_dir_all(dir.join("target/debug"))?;
+    fs::create_dir_all(dir.join("src/.git"))?;
+    fs::write(dir.join(".gitignore"), "target/\n*.log\n")?;
+    fs::write(dir.join("src/.gitignore"), "!keep.log\n")?;
+    for file in [
+        "fox.txt",
+        "fox.log",
+        "target/debug/fox.txt",
+        "src/keep.log",
+        "src/.git/fox",
+    ] {
+        fs::write(dir.join(file), "fox\n")?;
+    }
+
+    let output = Command::cargo_bin(PRG)?
+        .args(["-rl", "--respect-gitignore", "fox"])
+        .arg(&dir)
+        .output()?;
+    let mut files: Vec<_> = String::from_utf8(output.stdout)?
+        .lines()
+        .map(|file| file.replace(&dir.display().to_string(), ""))
+        .collect();
+    files.sort();
+    assert_eq!(files, ["/fox.txt", "/src/keep.log"]);
+
+    // Everything gets searched otherwise
+    Command::cargo_bin(PRG)?
+        .args(["-rc", "fox"])
+        .arg(&dir)
+        .assert()
+        .stdout(predicate::str::contains("target/debug/fox.txt:1"));
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_gitignore_above() -> TestResult {
+    // Searching a subdirectory still honors the ignore files of the
+    // repository it is in
+    let dir = std::env::temp_dir().join(format!("grepr-{}", gen_bad_file()));
+    fs::create_dir_all(dir.join(".git/info"))?;
+    fs::create_dir_all(dir.join("src/target"))?;
+    fs::write(dir.join(".git/info/exclude"), "excluded.txt\n")?;
+    fs::write(dir.join(".gitignore"), "target/\n*.log\n")?;
+    for file in ["fox.txt", "fox.log", "excluded.txt", "target/fox.txt"] {
+        fs::write(dir.join("src").join(file), "fox\n")?;
+    }
+
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(&dir)
+        .args(["-rl", "--respect-gitignore", "fox", "src"])
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    assert_eq!(String::from_utf8(output.stdout)?, "src/fox.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_total() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count-total", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY])
+        .assert()
+        .success()
+        .stdout("7\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--count-total", "cat", FOX])
+        .assert()
+        .code(1)
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_count_total_and_files_with_matches() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--count-total", "-l", "dog", FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "$2 $1", r"(\w+) (dog)", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the dog lazy.\n");
+
+    // Only the word itself gets replaced
+    Command::cargo_bin(PRG)?
+        .args(["-w", "--replace", "[$0]", "fox|the", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown [fox] jumps over [the] lazy dog.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp_adjacent() -> TestResult {
+    // A space between two words belongs to neither of them
+    Command::cargo_bin(PRG)?
+        .args(["-w", "--replace", "X", "foo"])
+        .write_stdin("foo foo\nfoo.foo-foobar\n")
+        .assert()
+        .success()
+        .stdout("X X\nX.X-foobar\n");
+
+    let foo = "\x1b[01;31m\x1b[Kfoo\x1b[m\x1b[K";
+    Command::cargo_bin(PRG)?
+        .args(["-w", "--color=always", "foo"])
+        .env_remove("GREP_COLORS")
+        .write_stdin("foo foo\n")
+        .assert()
+        .success()
+        .stdout(format!("{} {}\n", foo, foo));
+    Ok(())
+}
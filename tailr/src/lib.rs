@@ -1,13 +1,24 @@
+use crate::Headers::*;
 use crate::TakeValue::*;
+use ansi_term::Colour;
 use clap::{App, Arg};
+use memchr::memchr_iter;
 use once_cell::sync::OnceCell;
+use rayon::prelude::*;
 use regex::Regex;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::io::{self, BufRead, BufReader, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+const CHUNK_SIZE: usize = 64 * 1024;
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+
 static PLUS_OR_MINUS_RE: OnceCell<Regex> = OnceCell::new();
 static PLUS_ZERO_RE: OnceCell<Regex> = OnceCell::new();
 
@@ -17,12 +28,23 @@ enum TakeValue {
     TakeNum(i64),
 }
 
+#[derive(Debug, PartialEq)]
+enum Headers {
+    Auto,
+    Always,
+    Never,
+}
+
 #[derive(Debug)]
 pub struct Config {
     files: Vec<String>,
     lines: TakeValue,
     bytes: Option<TakeValue>,
-    quiet: bool,
+    headers: Headers,
+    follow: bool,
+    flush_delimiter: Option<String>,
+    color: bool,
+    highlight: Option<Regex>,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -55,7 +77,49 @@ pub fn get_args() -> MyResult<Config> {
                 .value_name("BYTES")
                 .conflicts_with("lines"),
         )
-        .arg(Arg::with_name("quiet").short("q").long("quiet"))
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Never print headers")
+                .overrides_with("verbose"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Always print headers")
+                .overrides_with("quiet"),
+        )
+        .arg(
+            Arg::with_name("follow")
+                .short("f")
+                .long("follow")
+                .help("Output appended data as the file grows"),
+        )
+        .arg(
+            Arg::with_name("flush_delimiter")
+                .long("flush-delimiter")
+                .help("Print SEP before output that follows an idle period")
+                .takes_value(true)
+                .value_name("SEP")
+                .min_values(0)
+                .require_equals(true)
+                .requires("follow"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .help("Colorize file headers"),
+        )
+        .arg(
+            Arg::with_name("highlight")
+                .long("highlight")
+                .help("Highlight lines matching REGEX")
+                .takes_value(true)
+                .value_name("REGEX")
+                .requires("color"),
+        )
         .get_matches();
 
     let files = matches.values_of_lossy("files").unwrap();
@@ -73,42 +137,391 @@ pub fn get_args() -> MyResult<Config> {
         .transpose()
         .map_err(|e| format!("illegal byte count -- {}", e))?;
 
-    let quiet = matches.is_present("quiet");
+    // -q and -v override each other, so only the last one given is present
+    let headers = if matches.is_present("quiet") {
+        Never
+    } else if matches.is_present("verbose") {
+        Always
+    } else {
+        Auto
+    };
+
+    let follow = matches.is_present("follow");
+    let flush_delimiter = if matches.is_present("flush_delimiter") {
+        Some(
+            matches
+                .value_of("flush_delimiter")
+                .unwrap_or("")
+                .to_string(),
+        )
+    } else {
+        None
+    };
+    let color = matches.is_present("color");
+
+    let highlight = matches
+        .value_of("highlight")
+        .map(|re| Regex::new(re).map_err(|_| format!("Invalid --highlight \"{}\"", re)))
+        .transpose()?;
 
     Ok(Config {
         files,
         lines,
         bytes,
-        quiet,
+        headers,
+        follow,
+        flush_delimiter,
+        color,
+        highlight,
     })
 }
 
 pub fn run(config: Config) -> MyResult<()> {
-    let num_files = config.files.len();
-
-    for (num, filename) in config.files.iter().enumerate() {
-        match File::open(&filename) {
-            Err(e) => eprintln!("{}: {}", filename, e),
-            Ok(file) => {
-                if !config.quiet && num_files > 1 {
-                    println!("{}==> {} <==", if num > 0 { "\n" } else { "" }, filename);
+    let show_headers = match config.headers {
+        Auto => config.files.len() > 1,
+        Always => true,
+        Never => false,
+    };
+
+    let take_val = config.bytes.as_ref().unwrap_or(&config.lines);
+    let mut stdout = io::stdout();
+    let (tx, rx) = mpsc::channel();
+    let mut followed = vec![];
+    let mut num = 0;
+
+    // Counting can be slow on networked filesystems, so where regular files
+    // start is found in parallel, for as many of them at a time as there are
+    // threads so as not to run out of file descriptors. Each file is then
+    // streamed straight to stdout, in order.
+    for files in config.files.chunks(rayon::current_num_threads()) {
+        let opened: Vec<_> = files
+            .par_iter()
+            .map(|filename| {
+                let opened = File::open(filename).map(|file| {
+                    // FIFOs and devices are left to tail_file()
+                    let start = file.metadata().map_err(|e| e.to_string()).and_then(|meta| {
+                        if meta.is_file() {
+                            find_start(filename, &config)
+                                .map(Some)
+                                .map_err(|e| e.to_string())
+                        } else {
+                            Ok(None)
+                        }
+                    });
+                    (file, start)
+                });
+
+                (filename.as_str(), opened)
+            })
+            .collect();
+
+        for (filename, opened) in opened {
+            num += 1;
+            let (file, start) = match opened {
+                Err(e) => {
+                    eprintln!("{}: {}", filename, e);
+                    continue;
                 }
+                Ok((file, start)) => (file, start?),
+            };
 
-                let file = BufReader::new(file);
-                let (total_lines, total_bytes) = count_lines_bytes(&filename)?;
+            if show_headers {
+                print_header(filename, num > 1, config.color);
+            }
 
-                if let Some(num_bytes) = &config.bytes {
-                    print_bytes(file, num_bytes, total_bytes)?;
+            let file = match start {
+                Some(start) => print_from(file, start, &config, &mut stdout)?,
+                None => tail_file(filename, file, &config, &mut stdout)?,
+            };
+
+            if config.follow {
+                if file.metadata()?.is_file() {
+                    followed.push((filename, Some(file)));
                 } else {
-                    print_lines(file, &config.lines, total_lines)?;
+                    let skip = get_offset_start(take_val);
+                    let last = match *take_val {
+                        TakeNum(n) if n < 0 => n.unsigned_abs(),
+                        _ => 0,
+                    };
+                    let bytes = config.bytes.is_some();
+                    stream(followed.len(), file, skip, last, bytes, tx.clone());
+                    followed.push((filename, None));
                 }
             }
         }
     }
 
+    // Only the streaming threads hold on to the channel from here on, so
+    // it disconnects once all of them reach EOF
+    drop(tx);
+
+    if config.follow && !followed.is_empty() {
+        follow(followed, rx, show_headers, &config)?;
+    }
+
     Ok(())
 }
 
+fn tail_file(filename: &str, file: File, config: &Config, out: &mut impl Write) -> MyResult<File> {
+    let take_val = config.bytes.as_ref().unwrap_or(&config.lines);
+    let pick_total = |(lines, bytes)| {
+        if config.bytes.is_some() {
+            bytes
+        } else {
+            lines
+        }
+    };
+
+    // FIFOs and devices can be neither counted nor seeked, so following
+    // one is left to stream()
+    if !file.metadata()?.is_file() {
+        if !config.follow {
+            let mut buf = Vec::new();
+            (&file).read_to_end(&mut buf)?;
+            let total = pick_total(count_reader(buf.as_slice())?);
+            let start = get_start_index(take_val, total);
+            print_take(Cursor::new(buf), start, config, out)?;
+        }
+
+        return Ok(file);
+    }
+
+    let start = find_start(filename, config)?;
+    print_from(file, start, config, out)
+}
+
+// Where the output of a regular file starts, which can take reading it all
+fn find_start(filename: &str, config: &Config) -> MyResult<Option<u64>> {
+    let take_val = config.bytes.as_ref().unwrap_or(&config.lines);
+
+    // Offsets from the start of the file don't depend on its size, so
+    // skip the pre-scan and start streaming right away
+    Ok(match get_offset_start(take_val) {
+        Some(start) => Some(start),
+        None => {
+            let (lines, bytes) = count_lines_bytes(filename)?;
+            let total = if config.bytes.is_some() { bytes } else { lines };
+            get_start_index(take_val, total)
+        }
+    })
+}
+
+// Prints a regular file from start on, leaving it at its end to follow
+fn print_from(
+    file: File,
+    start: Option<u64>,
+    config: &Config,
+    out: &mut impl Write,
+) -> MyResult<File> {
+    let mut file = BufReader::new(file);
+    print_take(&mut file, start, config, out)?;
+
+    let mut file = file.into_inner();
+    file.seek(SeekFrom::End(0))?;
+
+    Ok(file)
+}
+
+fn print_take<T: BufRead + Seek>(
+    file: T,
+    start: Option<u64>,
+    config: &Config,
+    out: &mut impl Write,
+) -> MyResult<()> {
+    if config.bytes.is_some() {
+        print_bytes(file, start, config.highlight.as_ref(), out)
+    } else {
+        print_lines(file, start, config.highlight.as_ref(), out)
+    }
+}
+
+fn print_header(filename: &str, separate: bool, color: bool) {
+    let header = format!("==> {} <==", filename);
+    println!(
+        "{}{}",
+        if separate { "\n" } else { "" },
+        if color {
+            Colour::Cyan.bold().paint(header).to_string()
+        } else {
+            header
+        }
+    );
+}
+
+// Streams a FIFO or device line by line, from an offset if there is one.
+// Otherwise the last lines or bytes are held back until the first EOF or
+// FOLLOW_INTERVAL without input, as only then is it known which they are
+fn stream(
+    num: usize,
+    file: File,
+    skip: Option<u64>,
+    last: u64,
+    bytes: bool,
+    tx: Sender<(usize, Vec<u8>)>,
+) {
+    let (lines_tx, lines_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut file = BufReader::new(file);
+
+        loop {
+            let mut buf = Vec::new();
+
+            match file.read_until(b'\n', &mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    break;
+                }
+            }
+
+            if lines_tx.send(buf).is_err() {
+                break;
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        let mut tailing = skip.is_none();
+        let mut skip = skip.unwrap_or(0);
+        let mut kept: VecDeque<Vec<u8>> = VecDeque::new();
+        let mut kept_len = 0;
+
+        loop {
+            let mut buf = if tailing {
+                match lines_rx.recv_timeout(FOLLOW_INTERVAL) {
+                    Ok(buf) => {
+                        kept_len += buf.len() as u64;
+                        kept.push_back(buf);
+                        keep_last(&mut kept, &mut kept_len, last, bytes);
+                        continue;
+                    }
+                    Err(e) => {
+                        tailing = false;
+                        let buf = kept.drain(..).flatten().collect::<Vec<_>>();
+                        if (!buf.is_empty() && tx.send((num, buf)).is_err())
+                            || e == RecvTimeoutError::Disconnected
+                        {
+                            break;
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                match lines_rx.recv() {
+                    Ok(buf) => buf,
+                    Err(_) => break,
+                }
+            };
+
+            if bytes {
+                let n = skip.min(buf.len() as u64);
+                buf.drain(..n as usize);
+                skip -= n;
+            } else if skip > 0 {
+                buf.clear();
+                skip -= 1;
+            }
+
+            if !buf.is_empty() && tx.send((num, buf)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+// Drops lines, or bytes, from the front until only the last ones are left
+fn keep_last(kept: &mut VecDeque<Vec<u8>>, kept_len: &mut u64, last: u64, bytes: bool) {
+    if !bytes {
+        while kept.len() as u64 > last {
+            kept.pop_front();
+        }
+        return;
+    }
+
+    while *kept_len > last {
+        let Some(front) = kept.front_mut() else {
+            break;
+        };
+        let n = (front.len() as u64).min(*kept_len - last);
+        front.drain(..n as usize);
+        *kept_len -= n;
+        if front.is_empty() {
+            kept.pop_front();
+        }
+    }
+}
+
+fn follow(
+    mut files: Vec<(&str, Option<File>)>,
+    rx: Receiver<(usize, Vec<u8>)>,
+    show_headers: bool,
+    config: &Config,
+) -> MyResult<()> {
+    let has_regular = files.iter().any(|(_, file)| file.is_some());
+
+    // The initial pass ended with the last file, so only print a header
+    // again once some other file produces output
+    let mut last = files.len() - 1;
+    let mut idle = false;
+
+    loop {
+        let mut chunks = vec![];
+
+        match rx.recv_timeout(FOLLOW_INTERVAL) {
+            Ok(chunk) => chunks.push(chunk),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if !has_regular {
+                    return Ok(());
+                }
+                thread::sleep(FOLLOW_INTERVAL);
+            }
+        }
+        chunks.extend(rx.try_iter());
+
+        for (num, (filename, file)) in files.iter_mut().enumerate() {
+            if let Some(file) = file {
+                let pos = file.stream_position()?;
+                if file.metadata()?.len() < pos {
+                    eprintln!("{}: file truncated", filename);
+                    file.seek(SeekFrom::Start(0))?;
+                }
+
+                let mut buf = Vec::new();
+                file.read_to_end(&mut buf)?;
+
+                if !buf.is_empty() {
+                    chunks.push((num, buf));
+                }
+            }
+        }
+
+        if idle && !chunks.is_empty() {
+            if let Some(delimiter) = &config.flush_delimiter {
+                println!("{}", delimiter);
+            }
+        }
+
+        for (num, buf) in &chunks {
+            if show_headers && *num != last {
+                print_header(files[*num].0, true, config.color);
+            }
+            last = *num;
+
+            print_text(
+                &String::from_utf8_lossy(buf),
+                config.highlight.as_ref(),
+                &mut io::stdout(),
+            )?;
+        }
+        io::stdout().flush()?;
+
+        idle = chunks.is_empty();
+    }
+}
+
 fn parse_num(val: &str) -> MyResult<TakeValue> {
     let plus_or_minus = PLUS_OR_MINUS_RE.get_or_init(|| Regex::new(r"^[+-][0-9]+$").unwrap());
     let plus_zero = PLUS_ZERO_RE.get_or_init(|| Regex::new(r"^[+]0$").unwrap());
@@ -122,29 +535,43 @@ fn parse_num(val: &str) -> MyResult<TakeValue> {
 }
 
 fn count_lines_bytes(filename: &str) -> MyResult<(i64, i64)> {
-    let mut file = BufReader::new(File::open(filename)?);
+    count_reader(File::open(filename)?)
+}
+
+fn count_reader(mut file: impl Read) -> MyResult<(i64, i64)> {
     let mut lines = 0;
     let mut bytes = 0;
-    let mut buf = String::new();
+    let mut last = b'\n';
+    let mut buf = vec![0; CHUNK_SIZE];
 
     loop {
-        let bytes_read = file.read_line(&mut buf)?;
-
-        if bytes_read == 0 {
-            break;
-        }
-
-        lines += 1;
+        let bytes_read = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        lines += memchr_iter(b'\n', &buf[..bytes_read]).count() as i64;
         bytes += bytes_read as i64;
+        last = buf[bytes_read - 1];
+    }
 
-        buf.clear();
+    // A final line without a trailing newline still counts
+    if last != b'\n' {
+        lines += 1;
     }
 
-    Ok((lines as i64, bytes as i64))
+    Ok((lines, bytes))
 }
 
-fn print_lines(mut file: impl BufRead, num_lines: &TakeValue, total_lines: i64) -> MyResult<()> {
-    if let Some(start) = get_start_index(num_lines, total_lines) {
+fn print_lines(
+    mut file: impl BufRead,
+    start: Option<u64>,
+    highlight: Option<&Regex>,
+    out: &mut impl Write,
+) -> MyResult<()> {
+    if let Some(start) = start {
         let mut line_num = 0;
         let mut buf = Vec::new();
 
@@ -156,7 +583,7 @@ fn print_lines(mut file: impl BufRead, num_lines: &TakeValue, total_lines: i64)
             }
 
             if line_num >= start {
-                print!("{}", String::from_utf8_lossy(&buf));
+                print_text(&String::from_utf8_lossy(&buf), highlight, out)?;
             }
 
             line_num += 1;
@@ -167,34 +594,72 @@ fn print_lines(mut file: impl BufRead, num_lines: &TakeValue, total_lines: i64)
     Ok(())
 }
 
-fn print_bytes<T: Read + Seek>(
+fn print_bytes<T: BufRead + Seek>(
     mut file: T,
-    num_bytes: &TakeValue,
-    total_bytes: i64,
+    start: Option<u64>,
+    highlight: Option<&Regex>,
+    out: &mut impl Write,
 ) -> MyResult<()> {
-    if let Some(start) = get_start_index(num_bytes, total_bytes) {
+    if let Some(start) = start {
         file.seek(SeekFrom::Start(start))?;
 
         let mut buf = Vec::new();
 
-        file.read_to_end(&mut buf)?;
+        // Newlines never split a UTF-8 sequence, so printing line by line
+        // keeps the output streaming without mangling multibyte characters
+        loop {
+            let bytes_read = file.read_until(b'\n', &mut buf)?;
+
+            if bytes_read == 0 {
+                break;
+            }
 
-        if !buf.is_empty() {
-            print!("{}", String::from_utf8_lossy(&buf));
+            print_text(&String::from_utf8_lossy(&buf), highlight, out)?;
+            buf.clear();
         }
     }
 
     Ok(())
 }
 
+fn print_text(text: &str, highlight: Option<&Regex>, out: &mut impl Write) -> io::Result<()> {
+    match highlight {
+        None => write!(out, "{}", text)?,
+        Some(re) => {
+            for line in text.split_inclusive('\n') {
+                let (content, newline) = match line.strip_suffix('\n') {
+                    Some(content) => (content, "\n"),
+                    None => (line, ""),
+                };
+
+                if re.is_match(content) {
+                    write!(out, "{}{}", Colour::Yellow.bold().paint(content), newline)?;
+                } else {
+                    write!(out, "{}", line)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn get_offset_start(take_val: &TakeValue) -> Option<u64> {
+    match *take_val {
+        TakeNum(n) if n > 0 => Some((n - 1) as u64),
+        PlusZero => Some(0),
+        TakeNum(_) => None,
+    }
+}
+
 fn get_start_index(take_val: &TakeValue, total: i64) -> Option<u64> {
-    match take_val {
-        &TakeNum(n) if n > 0 && n > total => None,
-        &TakeNum(n) if n < 0 && n.abs() > total => Some(0),
-        &TakeNum(n) if n > 0 => Some((n - 1) as u64),
-        &TakeNum(n) if n < 0 => Some((total + n) as u64),
-        &TakeNum(_) => None,
-        &PlusZero => {
+    match *take_val {
+        TakeNum(n) if n > 0 && n > total => None,
+        TakeNum(n) if n < 0 && n.abs() > total => Some(0),
+        TakeNum(n) if n > 0 => Some((n - 1) as u64),
+        TakeNum(n) if n < 0 => Some((total + n) as u64),
+        TakeNum(_) => None,
+        PlusZero => {
             if total == 0 {
                 None
             } else {
@@ -206,7 +671,10 @@ fn get_start_index(take_val: &TakeValue, total: i64) -> Option<u64> {
 
 #[cfg(test)]
 mod tests {
-    use super::{count_lines_bytes, get_start_index, parse_num, TakeValue::*};
+    use super::{
+        count_lines_bytes, count_reader, get_offset_start, get_start_index, parse_num, TakeValue::*,
+    };
+    use std::io::Cursor;
 
     #[test]
     fn test_get_start_index() {
@@ -243,6 +711,20 @@ mod tests {
         assert_eq!(get_start_index(&TakeNum(-20), 10), Some(0));
     }
 
+    #[test]
+    fn test_get_offset_start() {
+        // +0 and +1 both start at the beginning
+        assert_eq!(get_offset_start(&PlusZero), Some(0));
+        assert_eq!(get_offset_start(&TakeNum(1)), Some(0));
+
+        // Positive values start one before the given line/byte
+        assert_eq!(get_offset_start(&TakeNum(10)), Some(9));
+
+        // Zero and negative values need the total to be known
+        assert_eq!(get_offset_start(&TakeNum(0)), None);
+        assert_eq!(get_offset_start(&TakeNum(-3)), None);
+    }
+
     #[test]
     fn test_count_lines_bytes() {
         let res = count_lines_bytes("tests/inputs/one.txt");
@@ -254,6 +736,23 @@ mod tests {
         assert_eq!(res.unwrap(), (10, 49));
     }
 
+    #[test]
+    fn test_count_reader() {
+        let res = count_reader(Cursor::new(""));
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), (0, 0));
+
+        // A missing trailing newline still counts as a line
+        let res = count_reader(Cursor::new("a\nb"));
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), (2, 3));
+
+        // Invalid UTF-8 is not an error
+        let res = count_reader(Cursor::new(vec![0xff, b'\n', 0xfe, b'\n']));
+        assert!(res.is_ok());
+        assert_eq!(res.unwrap(), (2, 4));
+    }
+
     #[test]
     fn test_parse_num() {
         // All integers should be interpreted as negative numbers
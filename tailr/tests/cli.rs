@@ -2,7 +2,9 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
 use std::fs::{self, File};
-use std::io::Read;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -49,7 +51,7 @@ fn dies_bad_bytes() -> TestResult {
     let bad = random_string();
     let expected = format!("illegal byte count -- {}", &bad);
     Command::cargo_bin(PRG)?
-        .args(&["-c", &bad, EMPTY])
+        .args(["-c", &bad, EMPTY])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -63,7 +65,7 @@ fn dies_bad_lines() -> TestResult {
     let bad = random_string();
     let expected = format!("illegal line count -- {}", &bad);
     Command::cargo_bin(PRG)?
-        .args(&["-n", &bad, EMPTY])
+        .args(["-n", &bad, EMPTY])
         .assert()
         .failure()
         .stderr(predicate::str::contains(expected));
@@ -78,7 +80,7 @@ fn dies_bytes_and_lines() -> TestResult {
                used with '--bytes <BYTES>'";
 
     Command::cargo_bin(PRG)?
-        .args(&["-n", "1", "-c", "2"])
+        .args(["-n", "1", "-c", "2"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(msg));
@@ -92,7 +94,7 @@ fn skips_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .args(&[ONE, &bad, TWO])
+        .args([ONE, &bad, TWO])
         .assert()
         .stderr(predicate::str::is_match(expected)?);
 
@@ -758,6 +760,20 @@ fn multiple_files_n1_quiet() -> TestResult {
     )
 }
 
+#[test]
+fn many_files_in_order() -> TestResult {
+    // More files than there are threads to find where they start
+    let files = [TEN, EMPTY, ONE, THREE, TWO].repeat(40);
+    let expected = fs::read_to_string("tests/expected/all.n1.q.out")?.repeat(40);
+    Command::cargo_bin(PRG)?
+        .args(["-n", "1", "-q"])
+        .args(&files)
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
 #[test]
 fn multiple_files_n_minus_1() -> TestResult {
     run(
@@ -829,3 +845,253 @@ fn multiple_files_c_plus_3() -> TestResult {
         "tests/expected/all.c+3.out",
     )
 }
+
+// --------------------------------------------------
+#[test]
+fn color_headers() -> TestResult {
+    let expected = "\u{1b}[1;36m==> tests/inputs/one.txt <==\u{1b}[0m\n\
+                    Öne line, four wordś.\n\n\
+                    \u{1b}[1;36m==> tests/inputs/two.txt <==\u{1b}[0m\n\
+                    Four words.\n";
+    Command::cargo_bin(PRG)?
+        .args(["--color", "-n", "1", ONE, TWO])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_highlight() -> TestResult {
+    let expected = "eight\n\u{1b}[1;33mnine\u{1b}[0m\nten\n";
+    Command::cargo_bin(PRG)?
+        .args(["--color", "--highlight", "^n", "-n", "3", TEN])
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_highlight_without_color() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--highlight", "foo", TEN])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--color"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_highlight() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--color", "--highlight", "*", TEN])
+        .assert()
+        .failure()
+        .stderr("Invalid --highlight \"*\"\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_then_verbose() -> TestResult {
+    run(
+        &["-n", "1", "-q", "-v", TEN, EMPTY, ONE, THREE, TWO],
+        "tests/expected/all.n1.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn verbose_then_quiet() -> TestResult {
+    run(
+        &["-n", "1", "-v", "-q", TEN, EMPTY, ONE, THREE, TWO],
+        "tests/expected/all.n1.q.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn verbose_single_file() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-v", "-n", "1", TEN])
+        .assert()
+        .success()
+        .stdout("==> tests/inputs/ten.txt <==\nten\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+fn follow(extra: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let file1 = dir.join(random_string());
+    let file2 = dir.join(random_string());
+    fs::write(&file1, "one\n")?;
+    fs::write(&file2, "two\n")?;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(extra)
+        .args(["-f", "-n", "1"])
+        .arg(&file1)
+        .arg(&file2)
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+
+    thread::sleep(Duration::from_millis(1500));
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&file1)?
+        .write_all(b"three\n")?;
+    thread::sleep(Duration::from_millis(1500));
+
+    child.kill()?;
+    let output = child.wait_with_output()?;
+    fs::remove_file(&file1)?;
+    fs::remove_file(&file2)?;
+
+    Ok(String::from_utf8(output.stdout)?
+        .replace(file1.to_str().unwrap(), "FILE1")
+        .replace(file2.to_str().unwrap(), "FILE2"))
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_headers() -> TestResult {
+    assert_eq!(
+        follow(&[])?,
+        "==> FILE1 <==\none\n\n==> FILE2 <==\ntwo\n\n==> FILE1 <==\nthree\n"
+    );
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_quiet() -> TestResult {
+    assert_eq!(follow(&["-q"])?, "one\ntwo\nthree\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_flush_delimiter() -> TestResult {
+    assert_eq!(
+        follow(&["-q", "--flush-delimiter=--"])?,
+        "one\ntwo\n--\nthree\n"
+    );
+    assert_eq!(follow(&["-q", "--flush-delimiter"])?, "one\ntwo\n\nthree\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_flush_delimiter_without_follow() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--flush-delimiter", TEN])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--follow"));
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn pipe_n2() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "2", "/dev/stdin"])
+        .write_stdin("one\ntwo\nthree\n")
+        .assert()
+        .success()
+        .stdout("two\nthree\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_pipe() -> TestResult {
+    // A followed pipe is streamed until its writer goes away
+    Command::cargo_bin(PRG)?
+        .args(["-f", "-n", "+2", "/dev/stdin"])
+        .write_stdin("one\ntwo\nthree\n")
+        .timeout(Duration::from_secs(5))
+        .assert()
+        .success()
+        .stdout("two\nthree\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-f", "-c", "+6", "/dev/stdin"])
+        .write_stdin("one\ntwo\nthree\n")
+        .timeout(Duration::from_secs(5))
+        .assert()
+        .success()
+        .stdout("wo\nthree\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn follow_pipe_tail() -> TestResult {
+    // Only the last lines or bytes before the writer goes quiet are shown
+    let input: String = (1..=100).map(|n| format!("{}\n", n)).collect();
+    for (args, expected) in [
+        (["-n", "3"], "98\n99\n100\n"),
+        (["-n", "0"], ""),
+        (["-c", "4"], "100\n"),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(["-f", "/dev/stdin"])
+            .args(args)
+            .write_stdin(input.as_str())
+            .timeout(Duration::from_secs(5))
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+
+    // Then whatever comes next is streamed
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(["-f", "-n", "2", "/dev/stdin"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"one\ntwo\nthree\n")?;
+    thread::sleep(Duration::from_millis(1500));
+    stdin.write_all(b"four\n")?;
+    drop(stdin);
+
+    let output = child.wait_with_output()?;
+    assert_eq!(String::from_utf8(output.stdout)?, "two\nthree\nfour\n");
+
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn many_files_stay_ordered() -> TestResult {
+    let files = [TEN, EMPTY, ONE, THREE, TWO];
+    let mut args = vec!["-q", "-n", "1"];
+    args.extend(files.iter().cycle().take(files.len() * 40));
+
+    let expected = fs::read_to_string("tests/expected/all.n1.q.out")?.repeat(40);
+    Command::cargo_bin(PRG)?
+        .args(&args)
+        .assert()
+        .success()
+        .stdout(expected);
+
+    Ok(())
+}
@@ -1,25 +1,85 @@
 use clap::{App, Arg};
 use std::error::Error;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::ops::AddAssign;
+use std::os::unix::ffi::OsStrExt;
+use std::str;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+// How much of a file is counted at a time
+const CHUNK: usize = 128 * 1024;
+
+// The bytes separating words unless told otherwise, as for wc
+const WHITESPACE: [bool; 256] = byte_set(b" \t\n\x0b\x0c\r");
+
 #[derive(Debug)]
 pub struct Config {
-    files: Vec<String>,
+    files: Vec<OsString>,
+    counter: Counter,
+    format: Format,
+    label: Option<String>,
+    progress: bool,
+    // Whether STDIN is read for lack of files, which leaves it unnamed
+    unnamed: bool,
+}
+
+/// Which counts to make, and how, built up from `Counter::new()`
+///
+/// ```
+/// use wcr::Counter;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let reader = "one two\nthree\n".as_bytes();
+/// let counts = Counter::new().lines().words().count(reader)?;
+/// assert_eq!(counts.lines(), Some(2));
+/// assert_eq!(counts.words(), Some(3));
+/// // Only what was asked for gets counted
+/// assert_eq!(counts.bytes(), None);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Counter {
     lines: bool,
     words: bool,
     bytes: bool,
     chars: bool,
+    graphemes: bool,
+    delimiters: [bool; 256],
+    invalid: InvalidUtf8,
+}
+
+/// What a `Counter` counted, with `None` for what it wasn't asked to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Counts {
+    lines: Option<usize>,
+    words: Option<usize>,
+    bytes: Option<usize>,
+    chars: Option<usize>,
+    graphemes: Option<usize>,
+}
+
+/// How invalid UTF-8 counts towards characters
+#[derive(Debug, Clone, Copy)]
+pub enum InvalidUtf8 {
+    /// One replacement character for each invalid sequence
+    Replace,
+    /// A character for each byte of an invalid sequence
+    Bytes,
+    /// The input can't be counted
+    Error,
 }
 
-#[derive(PartialEq, Debug)]
-pub struct FileInfo {
-    num_lines: usize,
-    num_words: usize,
-    num_chars: usize,
-    num_bytes: usize,
+#[derive(Debug)]
+enum Format {
+    Columns,
+    Tsv,
+    Json,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -34,6 +94,14 @@ pub fn get_args() -> MyResult<Config> {
                 .multiple(true)
                 .default_value("-"),
         )
+        .arg(
+            Arg::with_name("files0_from")
+                .value_name("F")
+                .long("files0-from")
+                .help("Read input files from the NUL-terminated names in F")
+                .takes_value(true)
+                .conflicts_with("files"),
+        )
         .arg(
             Arg::with_name("lines")
                 .short("l")
@@ -46,6 +114,19 @@ pub fn get_args() -> MyResult<Config> {
                 .long("words")
                 .help("Show word count"),
         )
+        .arg(
+            Arg::with_name("word_delimiter")
+                .value_name("CHARS")
+                .long("word-delimiter")
+                .help("Separate words by any of CHARS and newlines instead of whitespace")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("csv")
+                .long("csv")
+                .help("Separate words by commas and newlines")
+                .conflicts_with("word_delimiter"),
+        )
         .arg(
             Arg::with_name("bytes")
                 .short("c")
@@ -59,151 +140,729 @@ pub fn get_args() -> MyResult<Config> {
                 .conflicts_with("bytes")
                 .help("Show character count"),
         )
+        .arg(
+            Arg::with_name("invalid_utf8")
+                .value_name("MODE")
+                .long("invalid-utf8")
+                .help("Count invalid UTF-8 as a replacement character, as bytes, or as an error")
+                .possible_values(&["replace", "bytes", "error"])
+                .default_value("replace"),
+        )
+        .arg(
+            Arg::with_name("graphemes")
+                .long("graphemes")
+                .help("Show grapheme cluster count"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .value_name("FORMAT")
+                .long("format")
+                .help("Output format, with tsv and json naming each count")
+                .possible_values(&["columns", "tsv", "json"])
+                .default_value("columns"),
+        )
+        .arg(
+            Arg::with_name("label")
+                .value_name("NAME")
+                .long("label")
+                .help("Name to show for STDIN, which otherwise has none")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("progress")
+                .long("progress")
+                .help("Show on STDERR how much of each file has been counted"),
+        )
         .get_matches();
 
-    let files = matches.values_of_lossy("files").unwrap();
-    let mut lines = matches.is_present("lines");
-    let mut words = matches.is_present("words");
-    let mut bytes = matches.is_present("bytes");
-    let chars = matches.is_present("chars");
+    let files = match matches.value_of("files0_from") {
+        Some(source) => read_files0(source)?,
+        None => matches
+            .values_of_os("files")
+            .unwrap()
+            .map(OsString::from)
+            .collect(),
+    };
+    let mut counter = Counter::new();
+    if matches.is_present("lines") {
+        counter = counter.lines();
+    }
+    if matches.is_present("words") {
+        counter = counter.words();
+    }
+    if matches.is_present("bytes") {
+        counter = counter.bytes();
+    }
+    if matches.is_present("chars") {
+        counter = counter.chars();
+    }
+    if matches.is_present("graphemes") {
+        counter = counter.graphemes();
+    }
+    if !(counter.lines || counter.words || counter.bytes || counter.chars || counter.graphemes) {
+        counter = counter.lines().words().bytes();
+    }
 
-    if [lines, words, bytes, chars].iter().all(|v| !v) {
-        lines = true;
-        words = true;
-        bytes = true;
+    if matches.is_present("csv") {
+        counter = counter.word_delimiters(b",");
+    } else if let Some(chars) = matches.value_of("word_delimiter") {
+        if !chars.is_ascii() {
+            return Err(format!("Word delimiters must be ASCII: \"{}\"", chars).into());
+        }
+        counter = counter.word_delimiters(chars.as_bytes());
     }
 
+    counter = counter.invalid_utf8(match matches.value_of("invalid_utf8") {
+        Some("bytes") => InvalidUtf8::Bytes,
+        Some("error") => InvalidUtf8::Error,
+        _ => InvalidUtf8::Replace,
+    });
+
     Ok(Config {
         files,
-        lines,
-        words,
-        chars,
-        bytes,
+        counter,
+        format: match matches.value_of("format") {
+            Some("tsv") => Format::Tsv,
+            Some("json") => Format::Json,
+            _ => Format::Columns,
+        },
+        label: matches.value_of("label").map(String::from),
+        progress: matches.is_present("progress"),
+        unnamed: matches.occurrences_of("files") == 0 && !matches.is_present("files0_from"),
     })
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let mut total = FileInfo {
-        num_lines: 0,
-        num_words: 0,
-        num_chars: 0,
-        num_bytes: 0,
-    };
+// Returns the exit status, 1 when any file couldn't be counted
+pub fn run(config: Config) -> MyResult<i32> {
+    // Nothing counted yet, but with the counts that will be
+    let mut total = config.counter.count(io::empty())?;
 
     let num_files = config.files.len();
+    let mut failed = false;
 
-    for filename in &config.files {
-        match open(filename) {
-            Err(err) => eprintln!("{}: {}", filename, err),
-            Ok(file) => {
-                if let Ok(info) = count(file) {
-                    total.num_lines += info.num_lines;
-                    total.num_words += info.num_words;
-                    total.num_chars += info.num_chars;
-                    total.num_bytes += info.num_bytes;
-
-                    println!(
-                        "{}{}{}{}{}",
-                        format_field(info.num_lines, config.lines),
-                        format_field(info.num_words, config.words),
-                        format_field(info.num_bytes, config.bytes),
-                        format_field(info.num_chars, config.chars),
-                        if filename == "-" {
-                            "".to_string()
-                        } else {
-                            format!(" {}", filename)
-                        }
-                    );
-                }
+    if let Format::Tsv = config.format {
+        let names: Vec<_> = shown(&total).iter().map(|(name, _)| *name).collect();
+        println!("file\t{}", names.join("\t"));
+    }
+
+    for path in &config.files {
+        let filename = &*path.to_string_lossy();
+        if path.is_empty() {
+            // As wc, which only gets to empty names in the --files0-from list
+            eprintln!("invalid zero-length file name");
+            failed = true;
+            continue;
+        }
+        let counted = open(path).and_then(|file| {
+            if config.progress {
+                config.counter.count(Progress::new(file, path, filename))
+            } else {
+                config.counter.count(file)
+            }
+        });
+        match counted {
+            Err(err) => {
+                eprintln!("{}: {}", filename, err);
+                failed = true;
+            }
+            Ok(counts) => {
+                total += &counts;
+
+                let name = match (filename, &config.label) {
+                    ("-", Some(label)) => label,
+                    _ => filename,
+                };
+                println!("{}", format_counts(&counts, Some(name), &config));
             }
         }
     }
 
-    if num_files > 1 {
-        println!(
-            "{}{}{}{} total",
-            format_field(total.num_lines, config.lines),
-            format_field(total.num_words, config.words),
-            format_field(total.num_bytes, config.bytes),
-            format_field(total.num_chars, config.chars),
-        );
+    // Structured output always has its totals, for tools to rely on
+    if num_files > 1 || !matches!(config.format, Format::Columns) {
+        println!("{}", format_counts(&total, None, &config));
+    }
+
+    Ok(if failed { 1 } else { 0 })
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Counter::new()
+    }
+}
+
+impl Counter {
+    /// A counter counting nothing until told what to, with words separated
+    /// by whitespace and invalid UTF-8 replaced
+    pub fn new() -> Counter {
+        Counter {
+            lines: false,
+            words: false,
+            bytes: false,
+            chars: false,
+            graphemes: false,
+            delimiters: WHITESPACE,
+            invalid: InvalidUtf8::Replace,
+        }
+    }
+
+    /// Counts newlines
+    pub fn lines(self) -> Counter {
+        Counter {
+            lines: true,
+            ..self
+        }
+    }
+
+    /// Counts words, runs of bytes other than the word delimiters
+    pub fn words(self) -> Counter {
+        Counter {
+            words: true,
+            ..self
+        }
+    }
+
+    /// Counts bytes
+    pub fn bytes(self) -> Counter {
+        Counter {
+            bytes: true,
+            ..self
+        }
     }
 
-    Ok(())
+    /// Counts UTF-8 characters
+    pub fn chars(self) -> Counter {
+        Counter {
+            chars: true,
+            ..self
+        }
+    }
+
+    /// Counts grapheme clusters, which is much slower than the rest
+    pub fn graphemes(self) -> Counter {
+        Counter {
+            graphemes: true,
+            ..self
+        }
+    }
+
+    /// Words become runs of bytes other than these and newlines, rather
+    /// than whitespace
+    pub fn word_delimiters(self, delimiters: &[u8]) -> Counter {
+        let mut delimiters = byte_set(delimiters);
+        delimiters[b'\n' as usize] = true;
+        Counter { delimiters, ..self }
+    }
+
+    /// How invalid UTF-8 counts towards characters
+    pub fn invalid_utf8(self, invalid: InvalidUtf8) -> Counter {
+        Counter { invalid, ..self }
+    }
+
+    /// Counts what was asked for in `reader`, failing if it can't be read
+    /// or, with `InvalidUtf8::Error`, holds invalid UTF-8 while counting
+    /// characters
+    pub fn count(&self, mut reader: impl BufRead) -> MyResult<Counts> {
+        let mut num_lines = 0;
+        let mut num_words = 0;
+        let mut num_bytes = 0;
+        let mut num_chars = 0;
+        let mut num_graphemes = 0;
+
+        // Whether the last byte of the previous chunk was inside a word
+        let mut in_word = false;
+        // The line the previous chunk ended in the middle of: as no cluster
+        // crosses a newline, graphemes are counted a whole line at a time
+        let mut partial = vec![];
+        // The start of a character the previous chunk ended in the middle of
+        let mut carry = vec![];
+
+        loop {
+            let chunk = reader.fill_buf()?;
+
+            if chunk.is_empty() {
+                break;
+            }
+
+            num_bytes += chunk.len();
+
+            if self.lines {
+                num_lines += bytecount::count(chunk, b'\n');
+            }
+
+            if self.chars {
+                let tail = if carry.is_empty() {
+                    count_chars(chunk, self.invalid, &mut num_chars)?
+                } else {
+                    carry.extend_from_slice(chunk);
+                    count_chars(&carry, self.invalid, &mut num_chars)?
+                };
+                if carry.is_empty() {
+                    carry.extend_from_slice(&chunk[chunk.len() - tail..]);
+                } else {
+                    carry.drain(..carry.len() - tail);
+                }
+            }
+
+            if self.words {
+                for &byte in chunk {
+                    let delimiter = self.delimiters[byte as usize];
+                    if !delimiter && !in_word {
+                        num_words += 1;
+                    }
+                    in_word = !delimiter;
+                }
+            }
+
+            if self.graphemes {
+                match chunk.iter().rposition(|&byte| byte == b'\n') {
+                    Some(end) => {
+                        partial.extend_from_slice(&chunk[..=end]);
+                        num_graphemes += count_graphemes(&partial);
+                        partial.clear();
+                        partial.extend_from_slice(&chunk[end + 1..]);
+                    }
+                    None => partial.extend_from_slice(chunk),
+                }
+            }
+
+            let len = chunk.len();
+            reader.consume(len);
+        }
+        num_graphemes += count_graphemes(&partial);
+        // A character cut short by the end of the input
+        if !carry.is_empty() {
+            num_chars += invalid_chars(carry.len(), self.invalid)?;
+        }
+
+        Ok(Counts {
+            lines: self.lines.then_some(num_lines),
+            words: self.words.then_some(num_words),
+            bytes: self.bytes.then_some(num_bytes),
+            chars: self.chars.then_some(num_chars),
+            graphemes: self.graphemes.then_some(num_graphemes),
+        })
+    }
 }
 
-pub fn count(mut file: impl BufRead) -> MyResult<FileInfo> {
-    let mut num_lines = 0;
-    let mut num_words = 0;
-    let mut num_bytes = 0;
-    let mut num_chars = 0;
+impl Counts {
+    /// The number of newlines, if counted
+    pub fn lines(&self) -> Option<usize> {
+        self.lines
+    }
+
+    /// The number of words, if counted
+    pub fn words(&self) -> Option<usize> {
+        self.words
+    }
+
+    /// The number of bytes, if counted
+    pub fn bytes(&self) -> Option<usize> {
+        self.bytes
+    }
 
-    let mut line = String::new();
+    /// The number of characters, if counted
+    pub fn chars(&self) -> Option<usize> {
+        self.chars
+    }
+
+    /// The number of grapheme clusters, if counted
+    pub fn graphemes(&self) -> Option<usize> {
+        self.graphemes
+    }
+}
 
+/// Adds up counts, as for the totals of several files
+impl AddAssign<&Counts> for Counts {
+    fn add_assign(&mut self, other: &Counts) {
+        let add = |a: Option<usize>, b: Option<usize>| match (a, b) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+        self.lines = add(self.lines, other.lines);
+        self.words = add(self.words, other.words);
+        self.bytes = add(self.bytes, other.bytes);
+        self.chars = add(self.chars, other.chars);
+        self.graphemes = add(self.graphemes, other.graphemes);
+    }
+}
+
+// Adds the characters in bytes to num_chars, returning how many bytes at the
+// end are a character that may yet be completed by what follows
+fn count_chars(mut bytes: &[u8], invalid: InvalidUtf8, num_chars: &mut usize) -> MyResult<usize> {
     loop {
-        let line_bytes = file.read_line(&mut line)?;
+        match str::from_utf8(bytes) {
+            Ok(_) => {
+                *num_chars += bytecount::num_chars(bytes);
+                return Ok(0);
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                *num_chars += bytecount::num_chars(valid);
+                match e.error_len() {
+                    None => return Ok(rest.len()),
+                    Some(len) => {
+                        *num_chars += invalid_chars(len, invalid)?;
+                        bytes = &rest[len..];
+                    }
+                }
+            }
+        }
+    }
+}
+
+// How many characters an invalid sequence of len bytes counts as
+fn invalid_chars(len: usize, invalid: InvalidUtf8) -> MyResult<usize> {
+    match invalid {
+        InvalidUtf8::Replace => Ok(1),
+        InvalidUtf8::Bytes => Ok(len),
+        InvalidUtf8::Error => Err(From::from("invalid UTF-8")),
+    }
+}
 
-        if line_bytes == 0 {
-            break;
+const fn byte_set(bytes: &[u8]) -> [bool; 256] {
+    let mut set = [false; 256];
+    let mut i = 0;
+    while i < bytes.len() {
+        set[bytes[i] as usize] = true;
+        i += 1;
+    }
+    set
+}
+
+fn count_graphemes(text: &[u8]) -> usize {
+    String::from_utf8_lossy(text).graphemes(true).count()
+}
+
+fn open(filename: &OsStr) -> MyResult<Box<dyn BufRead>> {
+    match filename.as_bytes() {
+        b"-" => Ok(Box::new(BufReader::with_capacity(CHUNK, io::stdin()))),
+        _ => Ok(Box::new(BufReader::with_capacity(
+            CHUNK,
+            File::open(filename)?,
+        ))),
+    }
+}
+
+// The file names in source, "-" being STDIN, each ended by a NUL. They're
+// kept as they are, not as UTF-8, empty ones included to be reported.
+fn read_files0(source: &str) -> MyResult<Vec<OsString>> {
+    let mut names = vec![];
+    open(source.as_ref())
+        .map_err(|e| format!("{}: {}", source, e))?
+        .read_to_end(&mut names)?;
+
+    // Only the last name may go without its NUL
+    if names.last() == Some(&b'\0') {
+        names.pop();
+    }
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(names
+        .split(|&byte| byte == b'\0')
+        .map(|name| OsStr::from_bytes(name).to_os_string())
+        .collect())
+}
+
+// Reports on STDERR how much of a file has been counted as it's read,
+// redrawing the bar every so often and clearing it once done
+struct Progress<'a, R> {
+    reader: R,
+    filename: &'a str,
+    size: Option<u64>,
+    done: u64,
+    last: Instant,
+    drawn: bool,
+}
+
+impl<'a, R> Progress<'a, R> {
+    fn new(reader: R, path: &OsStr, filename: &'a str) -> Progress<'a, R> {
+        // Only regular files have a size worth measuring against
+        let size = match filename {
+            "-" => None,
+            _ => fs::metadata(path)
+                .ok()
+                .filter(|meta| meta.is_file())
+                .map(|meta| meta.len()),
+        };
+        Progress {
+            reader,
+            filename,
+            size,
+            done: 0,
+            last: Instant::now(),
+            drawn: false,
         }
+    }
 
-        num_bytes += line_bytes;
-        num_lines += 1;
-        num_words += line.split_whitespace().count();
-        num_chars += line.chars().count();
+    fn advance(&mut self, amount: usize) {
+        self.done += amount as u64;
+        if self.last.elapsed() >= Duration::from_millis(200) {
+            eprint!("\r{}", progress_bar(self.filename, self.done, self.size));
+            self.drawn = true;
+            self.last = Instant::now();
+        }
+    }
+}
 
-        line.clear();
+impl<R: Read> Read for Progress<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let amount = self.reader.read(buf)?;
+        self.advance(amount);
+        Ok(amount)
     }
+}
 
-    Ok(FileInfo {
-        num_lines,
-        num_words,
-        num_bytes,
-        num_chars,
-    })
+impl<R: BufRead> BufRead for Progress<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.reader.consume(amount);
+        self.advance(amount);
+    }
+}
+
+impl<R> Drop for Progress<'_, R> {
+    fn drop(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+// "fox.txt [#####     ]  50% 24/48 bytes", or just the bytes without a size
+fn progress_bar(filename: &str, done: u64, size: Option<u64>) -> String {
+    const WIDTH: u64 = 30;
+    match size {
+        Some(size) if size > 0 => {
+            let filled = (done.min(size) * WIDTH / size) as usize;
+            format!(
+                "{} [{}{}] {:>3}% {}/{} bytes",
+                filename,
+                "#".repeat(filled),
+                " ".repeat(WIDTH as usize - filled),
+                done.min(size) * 100 / size,
+                done,
+                size
+            )
+        }
+        _ => format!("{} {} bytes", filename, done),
+    }
+}
+
+// The counts shown, in wc's order, along with their names
+fn shown(counts: &Counts) -> Vec<(&'static str, usize)> {
+    [
+        ("lines", counts.lines),
+        ("words", counts.words),
+        ("bytes", counts.bytes),
+        ("chars", counts.chars),
+        ("graphemes", counts.graphemes),
+    ]
+    .into_iter()
+    .filter_map(|(name, count)| count.map(|count| (name, count)))
+    .collect()
+}
+
+// A line for a file, or for the totals without one
+fn format_counts(counts: &Counts, filename: Option<&str>, config: &Config) -> String {
+    let counts = shown(counts);
+    match config.format {
+        Format::Columns => {
+            let fields: String = counts
+                .iter()
+                .map(|(_, count)| format_field(*count, true))
+                .collect();
+            match filename {
+                None => format!("{} total", fields),
+                Some("-") if config.unnamed => fields,
+                Some(filename) => format!("{} {}", fields, filename),
+            }
+        }
+        Format::Tsv => {
+            let fields: Vec<_> = counts.iter().map(|(_, count)| count.to_string()).collect();
+            format!("{}\t{}", filename.unwrap_or("total"), fields.join("\t"))
+        }
+        Format::Json => {
+            let mut fields = vec![match filename {
+                Some(filename) => format!("\"file\":{}", json_string(filename)),
+                None => "\"total\":true".to_string(),
+            }];
+            fields.extend(
+                counts
+                    .iter()
+                    .map(|(name, count)| format!("\"{}\":{}", name, count)),
+            );
+            format!("{{{}}}", fields.join(","))
+        }
+    }
+}
+
+// Quoted and escaped as a JSON string
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+fn format_field(field: usize, show: bool) -> String {
+    if show {
+        format!("{:>8}", field)
+    } else {
+        "".to_string()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{count, format_field, FileInfo};
-    use std::io::Cursor;
+    use super::{format_field, json_string, progress_bar, Counter, Counts, InvalidUtf8};
+    use std::io::{BufReader, Cursor};
+
+    // Every count but grapheme clusters
+    fn counter() -> Counter {
+        Counter::new().lines().words().bytes().chars()
+    }
+
+    fn counts(lines: usize, words: usize, bytes: usize, chars: usize) -> Counts {
+        Counts {
+            lines: Some(lines),
+            words: Some(words),
+            bytes: Some(bytes),
+            chars: Some(chars),
+            graphemes: None,
+        }
+    }
 
     #[test]
     fn test_count() {
         let text = "I don't want the world. I just want your half.\r\n";
-        let info = count(Cursor::new(text));
+        let info = counter().count(Cursor::new(text));
 
         assert!(info.is_ok());
+        assert_eq!(info.unwrap(), counts(1, 10, 48, 48));
 
-        let expected = FileInfo {
-            num_lines: 1,
-            num_words: 10,
-            num_chars: 48,
-            num_bytes: 48,
+        // Bytes and characters differ for multibyte UTF-8
+        let info = counter().count(Cursor::new("Atlamál in grœnlenzku\n"));
+        assert_eq!(info.unwrap(), counts(1, 3, 24, 22));
+    }
+
+    #[test]
+    fn test_count_selected() {
+        let info = Counter::new()
+            .lines()
+            .words()
+            .count(Cursor::new("one two\nthree\n"))
+            .unwrap();
+
+        assert_eq!(info.lines(), Some(2));
+        assert_eq!(info.words(), Some(3));
+        assert_eq!(info.bytes(), None);
+        assert_eq!(info.chars(), None);
+        assert_eq!(info.graphemes(), None);
+
+        // Totals only add up what was counted
+        let mut total = info.clone();
+        total += &info;
+        assert_eq!(total.lines(), Some(4));
+        assert_eq!(total.bytes(), None);
+    }
+
+    #[test]
+    fn test_count_chunks() {
+        // Words and characters split across chunks, and a last line with
+        // no newline, which wc doesn't count
+        let text = "hello wörld\n\tno newline";
+        let info = counter().count(BufReader::with_capacity(3, Cursor::new(text)));
+
+        assert_eq!(info.unwrap(), counts(1, 4, 24, 23));
+    }
+
+    #[test]
+    fn test_count_graphemes() {
+        // A family emoji, a flag and a combining accent, split across
+        // chunks, and CRLF being a single cluster
+        let text = "👨‍👩‍👧 🇵🇹 e\u{301}\r\nok";
+        let info = counter()
+            .graphemes()
+            .count(BufReader::with_capacity(4, Cursor::new(text)));
+
+        let expected = Counts {
+            graphemes: Some(8),
+            ..counts(1, 4, 35, 15)
         };
 
         assert_eq!(info.unwrap(), expected);
     }
 
     #[test]
-    fn test_format_field() {
-        assert_eq!(format_field(1, false), "");
-        assert_eq!(format_field(3, true), "       3");
-        assert_eq!(format_field(10, true), "      10");
+    fn test_json_string() {
+        assert_eq!(json_string("fox.txt"), "\"fox.txt\"");
+        assert_eq!(json_string("a \"b\"\\c\n"), "\"a \\\"b\\\"\\\\c\\n\"");
     }
-}
 
-fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+    #[test]
+    fn test_count_delimiters() {
+        // Empty fields aren't words, and newlines always separate them
+        let text = "a,b c,,d\ne,f\n";
+        let info = counter().word_delimiters(b",").count(Cursor::new(text));
+
+        assert_eq!(info.unwrap(), counts(2, 5, 13, 13));
     }
-}
 
-fn format_field(field: usize, show: bool) -> String {
-    if show {
-        format!("{:>8}", field)
-    } else {
-        format!("{}", "".to_string())
+    #[test]
+    fn test_count_invalid_utf8() {
+        // Two invalid bytes, a euro sign split across chunks and one cut
+        // short by the end
+        let text: &[u8] = b"a\xff\xfeb\xe2\x82\xacc\xe2\x82";
+        let chars = |invalid| {
+            Counter::new()
+                .chars()
+                .invalid_utf8(invalid)
+                .count(BufReader::with_capacity(2, Cursor::new(text)))
+                .map(|info| info.chars())
+        };
+
+        assert_eq!(chars(InvalidUtf8::Replace).unwrap(), Some(7));
+        assert_eq!(chars(InvalidUtf8::Bytes).unwrap(), Some(8));
+        assert!(chars(InvalidUtf8::Error).is_err());
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        assert_eq!(
+            progress_bar("fox.txt", 24, Some(48)),
+            format!(
+                "fox.txt [{}{}]  50% 24/48 bytes",
+                "#".repeat(15),
+                " ".repeat(15)
+            )
+        );
+        assert_eq!(
+            progress_bar("big.txt", 100, Some(100)),
+            format!("big.txt [{}] 100% 100/100 bytes", "#".repeat(30))
+        );
+        assert_eq!(progress_bar("-", 1234, None), "- 1234 bytes");
+    }
+
+    #[test]
+    fn test_format_field() {
+        assert_eq!(format_field(1, false), "");
+        assert_eq!(format_field(3, true), "       3");
+        assert_eq!(format_field(10, true), "      10");
     }
 }
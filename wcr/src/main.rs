@@ -1,6 +1,9 @@
 fn main() {
-    if let Err(e) = wcr::get_args().and_then(wcr::run) {
-        eprintln!("{}", e);
-        std::process::exit(1);
+    match wcr::get_args().and_then(wcr::run) {
+        Ok(status) => std::process::exit(status),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
     }
 }
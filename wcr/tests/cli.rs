@@ -29,7 +29,7 @@ fn gen_bad_file() -> String {
 #[test]
 fn dies_chars_and_bytes() -> TestResult {
     Command::cargo_bin(PRG)?
-        .args(&["-m", "-c"])
+        .args(["-m", "-c"])
         .assert()
         .failure()
         .stderr(predicate::str::contains(
@@ -55,10 +55,11 @@ fn skips_bad_file() -> TestResult {
     let bad = gen_bad_file();
     let expected = format!("{}: .* [(]os error 2[)]", bad);
     Command::cargo_bin(PRG)?
-        .arg(bad)
+        .args([&bad, FOX])
         .assert()
-        .success()
-        .stderr(predicate::str::is_match(expected)?);
+        .code(1)
+        .stderr(predicate::str::is_match(expected)?)
+        .stdout(predicate::str::contains(FOX));
     Ok(())
 }
 
@@ -128,6 +129,12 @@ fn atlamal_bytes() -> TestResult {
     run(&["-c", ATLAMAL], "tests/expected/atlamal.txt.c.out")
 }
 
+// --------------------------------------------------
+#[test]
+fn atlamal_chars() -> TestResult {
+    run(&["-m", ATLAMAL], "tests/expected/atlamal.txt.m.out")
+}
+
 // --------------------------------------------------
 #[test]
 fn atlamal_words() -> TestResult {
@@ -158,12 +165,23 @@ fn atlamal_bytes_lines() -> TestResult {
     run(&["-l", "-c", ATLAMAL], "tests/expected/atlamal.txt.cl.out")
 }
 
+// --------------------------------------------------
+#[test]
+fn atlamal_words_chars() -> TestResult {
+    run(&["-w", "-m", ATLAMAL], "tests/expected/atlamal.txt.wm.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn atlamal_chars_lines() -> TestResult {
+    run(&["-l", "-m", ATLAMAL], "tests/expected/atlamal.txt.ml.out")
+}
+
 // --------------------------------------------------
 #[test]
 fn atlamal_stdin() -> TestResult {
     let input = fs::read_to_string(ATLAMAL)?;
-    let expected =
-        fs::read_to_string("tests/expected/atlamal.txt.stdin.out")?;
+    let expected = fs::read_to_string("tests/expected/atlamal.txt.stdin.out")?;
     Command::cargo_bin(PRG)?
         .write_stdin(input)
         .assert()
@@ -195,6 +213,12 @@ fn test_all_bytes() -> TestResult {
     run(&["-c", EMPTY, FOX, ATLAMAL], "tests/expected/all.c.out")
 }
 
+// --------------------------------------------------
+#[test]
+fn test_all_chars() -> TestResult {
+    run(&["-m", EMPTY, FOX, ATLAMAL], "tests/expected/all.m.out")
+}
+
 // --------------------------------------------------
 #[test]
 fn test_all_words_bytes() -> TestResult {
@@ -212,3 +236,205 @@ fn test_all_words_lines() -> TestResult {
 fn test_all_bytes_lines() -> TestResult {
     run(&["-cl", EMPTY, FOX, ATLAMAL], "tests/expected/all.cl.out")
 }
+
+// --------------------------------------------------
+#[test]
+fn test_all_chars_lines() -> TestResult {
+    run(&["-ml", EMPTY, FOX, ATLAMAL], "tests/expected/all.ml.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn files0_from_stdin() -> TestResult {
+    let expected = fs::read_to_string("tests/expected/all.out")?;
+    Command::cargo_bin(PRG)?
+        .arg("--files0-from=-")
+        .write_stdin(format!("{}\0{}\0{}\0", EMPTY, FOX, ATLAMAL))
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files0_from_empty_name() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("--files0-from=-")
+        .write_stdin(format!("{}\0\0{}\0", FOX, FOX))
+        .assert()
+        .code(1)
+        .stderr("invalid zero-length file name\n")
+        .stdout(predicate::str::contains("fox.txt").count(2));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn files0_from_non_utf8_name() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = std::env::temp_dir().join(format!("wcr-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(OsStr::from_bytes(b"bad\xffname"));
+    fs::copy(FOX, &path)?;
+
+    let mut list = path.as_os_str().as_bytes().to_vec();
+    list.push(b'\0');
+    let output = Command::cargo_bin(PRG)?
+        .arg("--files0-from=-")
+        .write_stdin(list)
+        .output()?;
+    fs::remove_dir_all(&dir)?;
+
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("bad\u{fffd}name"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_files0_from_and_files() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--files0-from", "-", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn graphemes() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-m", "--graphemes"])
+        .write_stdin("👍🏽 ok\n")
+        .assert()
+        .success()
+        .stdout("       6       5\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn format_tsv() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "tsv", "-lc", FOX, EMPTY])
+        .assert()
+        .success()
+        .stdout(format!(
+            "file\tlines\tbytes\n{}\t1\t48\n{}\t0\t0\ntotal\t1\t48\n",
+            FOX, EMPTY
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn format_json() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "json", FOX])
+        .assert()
+        .success()
+        .stdout(format!(
+            "{{\"file\":\"{}\",\"lines\":1,\"words\":9,\"bytes\":48}}\n\
+             {{\"total\":true,\"lines\":1,\"words\":9,\"bytes\":48}}\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_label() -> TestResult {
+    // STDIN is only unnamed when read for lack of files
+    Command::cargo_bin(PRG)?
+        .write_stdin("foo bar\n")
+        .assert()
+        .success()
+        .stdout("       1       2       8\n");
+
+    Command::cargo_bin(PRG)?
+        .arg("-")
+        .write_stdin("foo bar\n")
+        .assert()
+        .success()
+        .stdout("       1       2       8 -\n");
+
+    Command::cargo_bin(PRG)?
+        .arg("--label=input")
+        .write_stdin("foo bar\n")
+        .assert()
+        .success()
+        .stdout("       1       2       8 input\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--label", "input", "-", FOX])
+        .write_stdin("foo bar\n")
+        .assert()
+        .success()
+        .stdout(format!(
+            "       1       2       8 input\n       1       9      48 {}\n       2      11      56 total\n",
+            FOX
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_delimiter() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-w", "--csv"])
+        .write_stdin("name,age\nJoe Bloggs,42\n")
+        .assert()
+        .success()
+        .stdout("       4\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-w", "--word-delimiter", "\t;"])
+        .write_stdin("a b\tc;d\n")
+        .assert()
+        .success()
+        .stdout("       3\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--word-delimiter", "é"])
+        .assert()
+        .failure()
+        .stderr("Word delimiters must be ASCII: \"é\"\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invalid_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("-m")
+        .write_stdin(b"caf\xe9\xff\n".as_slice())
+        .assert()
+        .success()
+        .stdout("       6\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-m", "--invalid-utf8", "bytes"])
+        .write_stdin(b"\xe2\x82 \xe2\x82\xac\n".as_slice())
+        .assert()
+        .success()
+        .stdout("       5\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-m", "--invalid-utf8=error", "-", FOX])
+        .write_stdin(b"caf\xe9\n".as_slice())
+        .assert()
+        .code(1)
+        .stderr("-: invalid UTF-8\n")
+        .stdout(format!("      48 {}\n      48 total\n", FOX));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn progress() -> TestResult {
+    // Counting is done long before the bar would first be drawn
+    run(&["--progress", ATLAMAL], "tests/expected/atlamal.txt.out")
+}